@@ -0,0 +1,375 @@
+use sfml::window::Key;
+
+// What a key does in the window.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    Pause,
+    GainUp,
+    GainDown,
+    SpeedDown,
+    SpeedUp,
+    PanLeft,
+    PanRight,
+    WidthDown,
+    WidthUp,
+    SeekBackward,
+    SeekForward,
+    TransposeUp,
+    TransposeDown,
+    TuneUp,
+    TuneDown,
+    SetLoopStart,
+    SetLoopEnd,
+    ClearLoop,
+    NextTrack,
+    PreviousTrack,
+    NextSoundFont,
+    OpenFile,
+    ForcePreset,
+    // The channel from 0 to 9.
+    Mute(usize),
+    Solo(usize),
+    ToggleEffects,
+    ReverbDown,
+    ReverbUp,
+    ChorusDown,
+    ChorusUp,
+    ToggleEq,
+    ToggleDcBlock,
+    // The band from 0 for the low shelf to 2 for the high shelf.
+    EqUp(usize),
+    EqDown(usize),
+    ToggleMono,
+    NextVisualization,
+    NextWaveformStyle,
+    ToggleStereo,
+    ZoomIn,
+    ZoomOut,
+    WaveformGainUp,
+    WaveformGainDown,
+    ToggleAutoWaveformGain,
+    NextFftWindow,
+    ToggleFreeze,
+    ToggleChannelMeters,
+    ToggleBeatPulse,
+    ToggleVelocityBrightness,
+    ToggleRenderLoads,
+    ToggleEventLog,
+    NextTheme,
+    ToggleHud,
+    ToggleFullscreen,
+    Screenshot,
+    ToggleRecording,
+    Panic,
+    ToggleHelp,
+}
+
+// A row of the help: the keys doing one thing or opposite things,
+// each with whether Shift has to be held for it.
+pub struct Binding {
+    pub label: &'static str,
+    pub description: &'static str,
+    pub keys: &'static [(Key, bool, Action)],
+}
+
+// Every key handled in the window, in the order they're shown in the help.
+pub const BINDINGS: &[Binding] = &[
+    Binding {
+        label: "Space",
+        description: "pause or resume",
+        keys: &[(Key::Space, false, Action::Pause)],
+    },
+    Binding {
+        label: "= / -",
+        description: "raise or lower the gain",
+        keys: &[
+            (Key::Equal, false, Action::GainUp),
+            (Key::Hyphen, false, Action::GainDown),
+        ],
+    },
+    Binding {
+        label: "[ / ]",
+        description: "slow down or speed up",
+        keys: &[
+            (Key::LBracket, false, Action::SpeedDown),
+            (Key::RBracket, false, Action::SpeedUp),
+        ],
+    },
+    Binding {
+        label: ", / .",
+        description: "pan left or right",
+        keys: &[
+            (Key::Comma, false, Action::PanLeft),
+            (Key::Period, false, Action::PanRight),
+        ],
+    },
+    Binding {
+        label: "Shift+, / .",
+        description: "narrow or widen the stereo image",
+        keys: &[
+            (Key::Comma, true, Action::WidthDown),
+            (Key::Period, true, Action::WidthUp),
+        ],
+    },
+    Binding {
+        label: "Left / Right",
+        description: "seek 5 seconds back or forward",
+        keys: &[
+            (Key::Left, false, Action::SeekBackward),
+            (Key::Right, false, Action::SeekForward),
+        ],
+    },
+    Binding {
+        label: "Up / Down",
+        description: "transpose up or down a semitone",
+        keys: &[
+            (Key::Up, false, Action::TransposeUp),
+            (Key::Down, false, Action::TransposeDown),
+        ],
+    },
+    Binding {
+        label: "Shift+Up/Down",
+        description: "tune up or down a cent",
+        keys: &[
+            (Key::Up, true, Action::TuneUp),
+            (Key::Down, true, Action::TuneDown),
+        ],
+    },
+    Binding {
+        label: "A / B / C",
+        description: "set the start or end of the repeat, or clear it",
+        keys: &[
+            (Key::A, false, Action::SetLoopStart),
+            (Key::B, false, Action::SetLoopEnd),
+            (Key::C, false, Action::ClearLoop),
+        ],
+    },
+    Binding {
+        label: "N / P",
+        description: "play the next or previous track",
+        keys: &[
+            (Key::N, false, Action::NextTrack),
+            (Key::P, false, Action::PreviousTrack),
+        ],
+    },
+    Binding {
+        label: "S",
+        description: "switch to the next SoundFont",
+        keys: &[(Key::S, false, Action::NextSoundFont)],
+    },
+    Binding {
+        label: "O",
+        description: "play a MIDI file typed on stdin",
+        keys: &[(Key::O, false, Action::OpenFile)],
+    },
+    Binding {
+        label: "I",
+        description: "force a preset on a channel typed on stdin",
+        keys: &[(Key::I, false, Action::ForcePreset)],
+    },
+    Binding {
+        label: "1-0",
+        description: "mute channels 1 to 10",
+        keys: &[
+            (Key::Num1, false, Action::Mute(0)),
+            (Key::Num2, false, Action::Mute(1)),
+            (Key::Num3, false, Action::Mute(2)),
+            (Key::Num4, false, Action::Mute(3)),
+            (Key::Num5, false, Action::Mute(4)),
+            (Key::Num6, false, Action::Mute(5)),
+            (Key::Num7, false, Action::Mute(6)),
+            (Key::Num8, false, Action::Mute(7)),
+            (Key::Num9, false, Action::Mute(8)),
+            (Key::Num0, false, Action::Mute(9)),
+        ],
+    },
+    Binding {
+        label: "Shift+1-0",
+        description: "solo channels 1 to 10",
+        keys: &[
+            (Key::Num1, true, Action::Solo(0)),
+            (Key::Num2, true, Action::Solo(1)),
+            (Key::Num3, true, Action::Solo(2)),
+            (Key::Num4, true, Action::Solo(3)),
+            (Key::Num5, true, Action::Solo(4)),
+            (Key::Num6, true, Action::Solo(5)),
+            (Key::Num7, true, Action::Solo(6)),
+            (Key::Num8, true, Action::Solo(7)),
+            (Key::Num9, true, Action::Solo(8)),
+            (Key::Num0, true, Action::Solo(9)),
+        ],
+    },
+    Binding {
+        label: "R",
+        description: "switch the reverb and chorus on or off",
+        keys: &[(Key::R, false, Action::ToggleEffects)],
+    },
+    Binding {
+        label: "F5 / F6",
+        description: "lower or raise the reverb send",
+        keys: &[
+            (Key::F5, false, Action::ReverbDown),
+            (Key::F6, false, Action::ReverbUp),
+        ],
+    },
+    Binding {
+        label: "F7 / F8",
+        description: "lower or raise the chorus send",
+        keys: &[
+            (Key::F7, false, Action::ChorusDown),
+            (Key::F8, false, Action::ChorusUp),
+        ],
+    },
+    Binding {
+        label: "E",
+        description: "switch the EQ on or off",
+        keys: &[(Key::E, false, Action::ToggleEq)],
+    },
+    Binding {
+        label: "Shift+E",
+        description: "switch the DC blocking on or off",
+        keys: &[(Key::E, true, Action::ToggleDcBlock)],
+    },
+    Binding {
+        label: "F2-F4",
+        description: "raise the low, mid or high band",
+        keys: &[
+            (Key::F2, false, Action::EqUp(0)),
+            (Key::F3, false, Action::EqUp(1)),
+            (Key::F4, false, Action::EqUp(2)),
+        ],
+    },
+    Binding {
+        label: "Shift+F2-F4",
+        description: "lower the low, mid or high band",
+        keys: &[
+            (Key::F2, true, Action::EqDown(0)),
+            (Key::F3, true, Action::EqDown(1)),
+            (Key::F4, true, Action::EqDown(2)),
+        ],
+    },
+    Binding {
+        label: "M",
+        description: "switch mono on or off",
+        keys: &[(Key::M, false, Action::ToggleMono)],
+    },
+    Binding {
+        label: "Tab",
+        description: "switch to the next visualization",
+        keys: &[(Key::Tab, false, Action::NextVisualization)],
+    },
+    Binding {
+        label: "L",
+        description: "switch the style of the waveform",
+        keys: &[(Key::L, false, Action::NextWaveformStyle)],
+    },
+    Binding {
+        label: "D",
+        description: "show the channels apart or together",
+        keys: &[(Key::D, false, Action::ToggleStereo)],
+    },
+    Binding {
+        label: "Z / X",
+        description: "zoom the waveform in or out",
+        keys: &[
+            (Key::Z, false, Action::ZoomIn),
+            (Key::X, false, Action::ZoomOut),
+        ],
+    },
+    Binding {
+        label: "K / J",
+        description: "raise or lower the height of the waveform",
+        keys: &[
+            (Key::K, false, Action::WaveformGainUp),
+            (Key::J, false, Action::WaveformGainDown),
+        ],
+    },
+    Binding {
+        label: "Shift+K",
+        description: "fit the height of the waveform to its peak",
+        keys: &[(Key::K, true, Action::ToggleAutoWaveformGain)],
+    },
+    Binding {
+        label: "W",
+        description: "switch to the next FFT window",
+        keys: &[(Key::W, false, Action::NextFftWindow)],
+    },
+    Binding {
+        label: "Backspace",
+        description: "freeze the waveform, as Pause also does",
+        keys: &[
+            (Key::Backspace, false, Action::ToggleFreeze),
+            (Key::Pause, false, Action::ToggleFreeze),
+        ],
+    },
+    Binding {
+        label: "G",
+        description: "show or hide the channel meters",
+        keys: &[(Key::G, false, Action::ToggleChannelMeters)],
+    },
+    Binding {
+        label: "T",
+        description: "switch the beat pulse on or off",
+        keys: &[(Key::T, false, Action::ToggleBeatPulse)],
+    },
+    Binding {
+        label: "V",
+        description: "make the waveform follow the velocity",
+        keys: &[(Key::V, false, Action::ToggleVelocityBrightness)],
+    },
+    Binding {
+        label: "U",
+        description: "show or hide the render load graph",
+        keys: &[(Key::U, false, Action::ToggleRenderLoads)],
+    },
+    Binding {
+        label: "F10",
+        description: "show or hide the log of the MIDI messages",
+        keys: &[(Key::F10, false, Action::ToggleEventLog)],
+    },
+    Binding {
+        label: "Y",
+        description: "switch to the next theme",
+        keys: &[(Key::Y, false, Action::NextTheme)],
+    },
+    Binding {
+        label: "H",
+        description: "show or hide the HUD",
+        keys: &[(Key::H, false, Action::ToggleHud)],
+    },
+    Binding {
+        label: "F11",
+        description: "switch fullscreen on or off",
+        keys: &[(Key::F11, false, Action::ToggleFullscreen)],
+    },
+    Binding {
+        label: "F12",
+        description: "save a screenshot",
+        keys: &[(Key::F12, false, Action::Screenshot)],
+    },
+    Binding {
+        label: "F9",
+        description: "start or stop recording the output to a WAV file",
+        keys: &[(Key::F9, false, Action::ToggleRecording)],
+    },
+    Binding {
+        label: "Escape",
+        description: "silence every channel, unless the help is shown",
+        keys: &[(Key::Escape, false, Action::Panic)],
+    },
+    Binding {
+        label: "F1",
+        description: "show or hide this help, which Escape also hides",
+        keys: &[(Key::F1, false, Action::ToggleHelp)],
+    },
+];
+
+// The action of the key, where the keys that don't need Shift also work with it
+// unless it does something else then.
+pub fn find_action(code: Key, shift: bool) -> Option<Action> {
+    let keys = || BINDINGS.iter().flat_map(|binding| binding.keys);
+    keys()
+        .find(|(key, with_shift, _)| *key == code && *with_shift == shift)
+        .or_else(|| keys().find(|(key, with_shift, _)| *key == code && !with_shift))
+        .map(|(_, _, action)| *action)
+}
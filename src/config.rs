@@ -0,0 +1,127 @@
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+// Defaults read from rustysynth-test.toml, which the command line overrides.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub window_width: u32,
+    pub window_height: u32,
+    pub background_color: [u8; 3],
+    // A fixed waveform color instead of the one following the activity.
+    pub waveform_color: Option<[u8; 3]>,
+    // The space in pixels left on each side of the waveform, which is dimmed.
+    pub waveform_margin: u32,
+    pub gain: f32,
+    #[serde(rename = "loop")]
+    pub play_loop: bool,
+    pub sample_rate: u32,
+    // The name of the color theme, which takes the place of the colors above.
+    // It's written back when another is picked in the window.
+    pub theme: Option<String>,
+    // The file the config was read from, if any.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            window_width: 1024,
+            window_height: 768,
+            background_color: [0, 32, 64],
+            waveform_color: None,
+            waveform_margin: 0,
+            gain: 1.0,
+            play_loop: false,
+            sample_rate: 44100,
+            theme: None,
+            path: None,
+        }
+    }
+}
+
+impl Config {
+    const FILE_NAME: &'static str = "rustysynth-test.toml";
+
+    // Reads the file from the current directory, or else from the user's
+    // config directory. Having neither is not an error.
+    pub fn load() -> Result<Self, String> {
+        for path in Config::candidate_paths() {
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => {
+                    return Err(format!("Failed to read '{}': {}", path.display(), error))
+                }
+            };
+            let mut config: Config = toml::from_str(&text)
+                .map_err(|error| format!("Failed to parse '{}': {}", path.display(), error))?;
+            config.path = Some(path);
+            return Ok(config);
+        }
+        Ok(Config::default())
+    }
+
+    // Sets the theme in the file the config was read from, or else in a new one
+    // in the user's config directory. Only the line of the theme is changed,
+    // so that the rest of the file stays as it was written.
+    pub fn save_theme(&self, theme: &str) -> Result<(), String> {
+        let path = match &self.path {
+            Some(path) => path.clone(),
+            None => match config_home() {
+                Some(config_home) => config_home.join(Config::FILE_NAME),
+                None => return Err("No config directory to save the theme in.".to_string()),
+            },
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(format!("Failed to read '{}': {}", path.display(), error)),
+        };
+
+        let line = format!("theme = \"{}\"", theme);
+        let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+        let is_theme = |line: &String| {
+            line.split_once('=')
+                .is_some_and(|(key, _)| key.trim() == "theme")
+        };
+        match lines.iter().position(is_theme) {
+            Some(index) => lines[index] = line,
+            // The keys after a table header would belong to the table, so it goes first.
+            None => lines.insert(0, line),
+        }
+        let mut text = lines.join("\n");
+        text.push('\n');
+
+        if let Some(directory) = path
+            .parent()
+            .filter(|directory| !directory.as_os_str().is_empty())
+        {
+            fs::create_dir_all(directory).map_err(|error| {
+                format!("Failed to create '{}': {}", directory.display(), error)
+            })?;
+        }
+        fs::write(&path, text)
+            .map_err(|error| format!("Failed to write '{}': {}", path.display(), error))
+    }
+
+    fn candidate_paths() -> Vec<PathBuf> {
+        let mut paths = vec![PathBuf::from(Config::FILE_NAME)];
+        if let Some(config_home) = config_home() {
+            paths.push(config_home.join(Config::FILE_NAME));
+        }
+        paths
+    }
+}
+
+// The user's config directory, following the XDG convention.
+pub fn config_home() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(value) if !value.is_empty() => Some(PathBuf::from(value)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+    }
+}
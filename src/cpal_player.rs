@@ -0,0 +1,137 @@
+use cpal::traits::DeviceTrait;
+use cpal::traits::HostTrait;
+use cpal::traits::StreamTrait;
+use sfml::audio::SoundStatus;
+use sfml::audio::SoundStream;
+use sfml::system::Time;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// Plays a SoundStream through cpal, with the same controls as SFML's
+// SoundStreamPlayer. The batches are pulled from the device's callback
+// as they are needed instead of being queued ahead.
+pub struct CpalPlayer<S: SoundStream + Send + 'static> {
+    source: Arc<Mutex<Source<S>>>,
+    stream: cpal::Stream,
+}
+
+struct Source<S: SoundStream> {
+    sound_stream: S,
+    batch: Vec<i16>,
+    position: usize,
+    playing: bool,
+    finished: bool,
+}
+
+impl<S: SoundStream> Source<S> {
+    // Copies the batches into the device's buffer, fetching new ones as it runs out of them.
+    fn fill(&mut self, output: &mut [f32]) {
+        let mut wrote = 0;
+        while wrote < output.len() {
+            if self.position == self.batch.len() {
+                if self.finished {
+                    self.playing = false;
+                }
+                if !self.playing {
+                    output[wrote..].fill(0_f32);
+                    return;
+                }
+                let (batch, keep_playing) = self.sound_stream.get_data();
+                self.batch.clear();
+                self.batch.extend_from_slice(batch);
+                self.position = 0;
+                self.finished = !keep_playing;
+                continue;
+            }
+
+            let rem = (output.len() - wrote).min(self.batch.len() - self.position);
+            for t in 0..rem {
+                output[wrote + t] = self.batch[self.position + t] as f32 / 32768_f32;
+            }
+            self.position += rem;
+            wrote += rem;
+        }
+    }
+}
+
+// The rate the default output device runs at by itself, if there's one to ask.
+pub fn default_sample_rate() -> Option<u32> {
+    let device = cpal::default_host().default_output_device()?;
+    let config = device.default_output_config().ok()?;
+    Some(config.sample_rate().0)
+}
+
+impl<S: SoundStream + Send + 'static> CpalPlayer<S> {
+    pub fn new(sound_stream: S, sample_rate: u32) -> Result<Self, String> {
+        let host = cpal::default_host();
+        let Some(device) = host.default_output_device() else {
+            return Err("No audio output device is available.".to_string());
+        };
+
+        let config = cpal::StreamConfig {
+            channels: 2,
+            sample_rate: cpal::SampleRate(sample_rate),
+            buffer_size: cpal::BufferSize::Default,
+        };
+
+        let source = Arc::new(Mutex::new(Source {
+            sound_stream: sound_stream,
+            batch: Vec::new(),
+            position: 0,
+            playing: false,
+            finished: false,
+        }));
+
+        let callback_source = Arc::clone(&source);
+        let stream = device
+            .build_output_stream(
+                &config,
+                move |output: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    callback_source.lock().unwrap().fill(output)
+                },
+                |error| eprintln!("Audio stream error: {}", error),
+                None,
+            )
+            .map_err(|error| format!("Failed to open the audio stream: {}", error))?;
+
+        Ok(Self {
+            source: source,
+            stream: stream,
+        })
+    }
+
+    // Playing again after the stream has finished asks it for more data,
+    // as SoundStreamPlayer does.
+    pub fn play(&mut self) {
+        let mut source = self.source.lock().unwrap();
+        source.playing = true;
+        source.finished = false;
+        drop(source);
+        if let Err(error) = self.stream.play() {
+            eprintln!("Failed to start the audio stream: {}", error);
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.source.lock().unwrap().playing = false;
+        if let Err(error) = self.stream.pause() {
+            eprintln!("Failed to stop the audio stream: {}", error);
+        }
+    }
+
+    pub fn status(&self) -> SoundStatus {
+        if self.source.lock().unwrap().playing {
+            SoundStatus::PLAYING
+        } else {
+            SoundStatus::STOPPED
+        }
+    }
+
+    pub fn set_playing_offset(&mut self, offset: Time) {
+        let mut source = self.source.lock().unwrap();
+        source.sound_stream.seek(offset);
+        source.batch.clear();
+        source.position = 0;
+        source.finished = false;
+    }
+}
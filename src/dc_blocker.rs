@@ -0,0 +1,40 @@
+use std::f32::consts::PI;
+
+// A one-pole high-pass for the left and right channels that takes out the DC offset,
+// with the corner low enough to leave the lowest notes as they are.
+// The filter states are kept between the calls to process so that
+// consecutive batches join up without clicks.
+pub struct DcBlocker {
+    // The pole, just inside the unit circle.
+    pole: f32,
+    // The last input and output of each channel.
+    states: [(f32, f32); 2],
+}
+
+impl DcBlocker {
+    // The corner frequency in Hz, where the response is down by 3 dB.
+    const CUTOFF: f32 = 5_f32;
+
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            pole: 1_f32 - 2_f32 * PI * DcBlocker::CUTOFF / sample_rate as f32,
+            states: [(0_f32, 0_f32); 2],
+        }
+    }
+
+    // Clears the filter states, so that stale samples don't leak into the output
+    // when the filter is switched back on.
+    pub fn reset(&mut self) {
+        self.states = [(0_f32, 0_f32); 2];
+    }
+
+    pub fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        for ((input, output), samples) in self.states.iter_mut().zip([left, right]) {
+            for value in samples.iter_mut() {
+                *output = *value - *input + self.pole * *output;
+                *input = *value;
+                *value = *output;
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+// Brings a stereo signal rendered at a multiple of the output rate back down to it,
+// filtering out what's above the output's Nyquist frequency before every few samples
+// are kept, so that it doesn't fold back as aliasing. The last samples of each batch
+// are kept for the filter to run into the next one, so that the batches join up seamlessly.
+pub struct Decimator {
+    factor: usize,
+    coefficients: Vec<f32>,
+    // The samples kept from the previous batch followed by the current one.
+    buffers: [Vec<f32>; 2],
+}
+
+impl Decimator {
+    // The length of the filter for each step of the factor, which leaves the delay
+    // at 12 samples of the output rate whatever the factor.
+    const TAPS_PER_FACTOR: usize = 24;
+    // A little below the output's Nyquist frequency, as the filter doesn't fall off at once.
+    const CUTOFF: f64 = 0.45;
+
+    pub fn new(factor: u32) -> Self {
+        let factor = factor as usize;
+        let length = Decimator::TAPS_PER_FACTOR * factor + 1;
+        Self {
+            factor: factor,
+            coefficients: lowpass(length, Decimator::CUTOFF / factor as f64),
+            buffers: [vec![0_f32; length - 1], vec![0_f32; length - 1]],
+        }
+    }
+
+    // Fills the outputs, which are shorter than the inputs by the factor.
+    pub fn process(
+        &mut self,
+        left: &[f32],
+        right: &[f32],
+        output_left: &mut [f32],
+        output_right: &mut [f32],
+    ) {
+        for ((buffer, input), output) in self
+            .buffers
+            .iter_mut()
+            .zip([left, right])
+            .zip([output_left, output_right])
+        {
+            buffer.extend_from_slice(input);
+            for (index, value) in output.iter_mut().enumerate() {
+                let start = index * self.factor;
+                let window = &buffer[start..start + self.coefficients.len()];
+                *value = window
+                    .iter()
+                    .zip(&self.coefficients)
+                    .map(|(sample, coefficient)| sample * coefficient)
+                    .sum();
+            }
+            buffer.drain(..input.len());
+        }
+    }
+}
+
+// A windowed-sinc lowpass with the cutoff in cycles per sample, using the Blackman window
+// and scaled to leave a constant signal as it is.
+fn lowpass(length: usize, cutoff: f64) -> Vec<f32> {
+    let center = (length - 1) as f64 / 2.0;
+    let mut coefficients: Vec<f64> = (0..length)
+        .map(|index| {
+            let x = index as f64 - center;
+            let sinc = match x == 0.0 {
+                true => 2.0 * cutoff,
+                false => {
+                    (2.0 * std::f64::consts::PI * cutoff * x).sin() / (std::f64::consts::PI * x)
+                }
+            };
+            let phase = 2.0 * std::f64::consts::PI * index as f64 / (length - 1) as f64;
+            let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+            sinc * window
+        })
+        .collect();
+    let sum: f64 = coefficients.iter().sum();
+    coefficients.iter_mut().for_each(|value| *value /= sum);
+    coefficients.iter().map(|&value| value as f32).collect()
+}
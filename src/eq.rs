@@ -0,0 +1,130 @@
+use std::f32::consts::PI;
+
+// A low shelf, a peak and a high shelf, for the left and right channels.
+// The filter states are kept between the calls to process so that
+// consecutive batches join up without clicks.
+pub struct Equalizer {
+    sample_rate: f32,
+    gains: [f32; 3],
+    filters: [[Biquad; 2]; 3],
+}
+
+impl Equalizer {
+    // The corner frequencies of the shelves and the center of the peak, in Hz.
+    pub const FREQUENCIES: [f32; 3] = [120_f32, 1000_f32, 8000_f32];
+
+    const PEAK_Q: f32 = 0.7_f32;
+
+    pub fn new(sample_rate: u32) -> Self {
+        let mut equalizer = Self {
+            sample_rate: sample_rate as f32,
+            gains: [0_f32; 3],
+            filters: [[Biquad::default(); 2]; 3],
+        };
+        equalizer.update_coefficients();
+        equalizer
+    }
+
+    // Sets the gains of the bands in dB, keeping the filter states.
+    pub fn set_gains(&mut self, gains: [f32; 3]) {
+        if gains != self.gains {
+            self.gains = gains;
+            self.update_coefficients();
+        }
+    }
+
+    // Clears the filter states, so that stale samples don't leak into the output
+    // when the EQ is switched back on.
+    pub fn reset(&mut self) {
+        for filter in self.filters.iter_mut().flatten() {
+            filter.z1 = 0_f32;
+            filter.z2 = 0_f32;
+        }
+    }
+
+    pub fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        for [filter_left, filter_right] in self.filters.iter_mut() {
+            left.iter_mut()
+                .for_each(|value| *value = filter_left.process(*value));
+            right
+                .iter_mut()
+                .for_each(|value| *value = filter_right.process(*value));
+        }
+    }
+
+    // Follows the formulas of the Audio EQ Cookbook, with the shelves at a slope of 1.
+    fn update_coefficients(&mut self) {
+        for band in 0..3 {
+            // Keep the frequencies below Nyquist for low sample rates.
+            let frequency = Equalizer::FREQUENCIES[band].min(0.45_f32 * self.sample_rate);
+            let w0 = 2_f32 * PI * frequency / self.sample_rate;
+            let (sin, cos) = w0.sin_cos();
+            let a = 10_f32.powf(self.gains[band] / 40_f32);
+
+            let [b0, b1, b2, a0, a1, a2] = match band {
+                0 => {
+                    let alpha = 2_f32 * a.sqrt() * sin / 2_f32.sqrt();
+                    [
+                        a * ((a + 1_f32) - (a - 1_f32) * cos + alpha),
+                        2_f32 * a * ((a - 1_f32) - (a + 1_f32) * cos),
+                        a * ((a + 1_f32) - (a - 1_f32) * cos - alpha),
+                        (a + 1_f32) + (a - 1_f32) * cos + alpha,
+                        -2_f32 * ((a - 1_f32) + (a + 1_f32) * cos),
+                        (a + 1_f32) + (a - 1_f32) * cos - alpha,
+                    ]
+                }
+                1 => {
+                    let alpha = sin / (2_f32 * Equalizer::PEAK_Q);
+                    [
+                        1_f32 + alpha * a,
+                        -2_f32 * cos,
+                        1_f32 - alpha * a,
+                        1_f32 + alpha / a,
+                        -2_f32 * cos,
+                        1_f32 - alpha / a,
+                    ]
+                }
+                _ => {
+                    let alpha = 2_f32 * a.sqrt() * sin / 2_f32.sqrt();
+                    [
+                        a * ((a + 1_f32) + (a - 1_f32) * cos + alpha),
+                        -2_f32 * a * ((a - 1_f32) + (a + 1_f32) * cos),
+                        a * ((a + 1_f32) + (a - 1_f32) * cos - alpha),
+                        (a + 1_f32) - (a - 1_f32) * cos + alpha,
+                        2_f32 * ((a - 1_f32) - (a + 1_f32) * cos),
+                        (a + 1_f32) - (a - 1_f32) * cos - alpha,
+                    ]
+                }
+            };
+
+            for filter in self.filters[band].iter_mut() {
+                filter.b0 = b0 / a0;
+                filter.b1 = b1 / a0;
+                filter.b2 = b2 / a0;
+                filter.a1 = a1 / a0;
+                filter.a2 = a2 / a0;
+            }
+        }
+    }
+}
+
+// A second-order filter in the transposed direct form II.
+#[derive(Clone, Copy, Default)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    fn process(&mut self, input: f32) -> f32 {
+        let output = self.b0 * input + self.z1;
+        self.z1 = self.b1 * input - self.a1 * output + self.z2;
+        self.z2 = self.b2 * input - self.a2 * output;
+        output
+    }
+}
@@ -0,0 +1,145 @@
+use std::f32::consts::PI;
+
+// The level reported for bins with no energy.
+pub const SILENCE_DB: f32 = -120_f32;
+
+// In-place iterative radix-2 FFT. The length must be a power of two.
+pub fn fft(real: &mut [f32], imag: &mut [f32]) {
+    let n = real.len();
+    assert!(n.is_power_of_two() && imag.len() == n);
+
+    // Reorder the input in bit-reversed order.
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            real.swap(i, j);
+            imag.swap(i, j);
+        }
+    }
+
+    let mut length = 2;
+    while length <= n {
+        let half = length / 2;
+        let angle = -2_f32 * PI / length as f32;
+        for start in (0..n).step_by(length) {
+            for k in 0..half {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let a = start + k;
+                let b = a + half;
+                let tr = real[b] * cos - imag[b] * sin;
+                let ti = real[b] * sin + imag[b] * cos;
+                real[b] = real[a] - tr;
+                imag[b] = imag[a] - ti;
+                real[a] += tr;
+                imag[a] += ti;
+            }
+        }
+        length <<= 1;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+}
+
+impl Window {
+    pub fn next(self) -> Self {
+        match self {
+            Window::Rectangular => Window::Hann,
+            Window::Hann => Window::Hamming,
+            Window::Hamming => Window::Blackman,
+            Window::Blackman => Window::Rectangular,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Window::Rectangular => "rectangular",
+            Window::Hann => "Hann",
+            Window::Hamming => "Hamming",
+            Window::Blackman => "Blackman",
+        }
+    }
+
+    // The periodic form of the window, which suits spectral analysis.
+    fn coefficients(self, n: usize) -> Vec<f32> {
+        (0..n)
+            .map(|i| {
+                let x = 2_f32 * PI * i as f32 / n as f32;
+                match self {
+                    Window::Rectangular => 1_f32,
+                    Window::Hann => 0.5_f32 - 0.5_f32 * x.cos(),
+                    Window::Hamming => 0.54_f32 - 0.46_f32 * x.cos(),
+                    Window::Blackman => 0.42_f32 - 0.5_f32 * x.cos() + 0.08_f32 * (2_f32 * x).cos(),
+                }
+            })
+            .collect()
+    }
+}
+
+// Keeps the coefficients of the selected window for the length last asked for,
+// so that they aren't recomputed for every spectrum.
+pub struct WindowCache {
+    window: Window,
+    coefficients: Vec<f32>,
+}
+
+impl WindowCache {
+    pub fn new(window: Window) -> Self {
+        Self {
+            window: window,
+            coefficients: Vec::new(),
+        }
+    }
+
+    pub fn get_window(&self) -> Window {
+        self.window
+    }
+
+    pub fn set_window(&mut self, value: Window) {
+        if value != self.window {
+            self.window = value;
+            self.coefficients.clear();
+        }
+    }
+
+    pub fn get_coefficients(&mut self, n: usize) -> &[f32] {
+        if self.coefficients.len() != n {
+            self.coefficients = self.window.coefficients(n);
+        }
+        &self.coefficients
+    }
+}
+
+// Returns the magnitude of each bin up to the Nyquist frequency in dB,
+// with the samples weighted by the window coefficients.
+// A full-scale sine wave reads 0 dB whichever window is used.
+pub fn spectrum_db(samples: &[f32], window: &[f32]) -> Vec<f32> {
+    let n = samples.len();
+    let mut real: Vec<f32> = samples.iter().zip(window).map(|(s, w)| s * w).collect();
+    let mut imag = vec![0_f32; n];
+    fft(&mut real, &mut imag);
+
+    // Compensate for the window's attenuation of the signal.
+    let scale = 2_f32 / window.iter().sum::<f32>();
+    (0..n / 2)
+        .map(|i| {
+            let magnitude = scale * (real[i] * real[i] + imag[i] * imag[i]).sqrt();
+            if magnitude > 0_f32 {
+                (20_f32 * magnitude.log10()).max(SILENCE_DB)
+            } else {
+                SILENCE_DB
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,25 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+// Whether the messages that only tell what's going on are left out,
+// so that nothing but the errors, the warnings and the prompts reach stderr.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+// Prints a line to stderr like eprintln!, unless --quiet has been given.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !crate::log::is_quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use info;
@@ -1,75 +1,1127 @@
+mod bindings;
+mod config;
+#[cfg(feature = "cpal")]
+mod cpal_player;
+mod dc_blocker;
+mod decimator;
+mod eq;
+mod fft;
+mod gm;
+mod log;
+mod midi;
+#[cfg(feature = "midi-in")]
+mod midi_input;
+mod note_keys;
+mod options;
+mod positions;
+mod ramp;
+mod resampler;
+mod sequencer;
+mod theme;
+mod wav;
+
+use crate::bindings::Action;
+use crate::bindings::BINDINGS;
+use crate::config::Config;
+#[cfg(feature = "cpal")]
+use crate::cpal_player::CpalPlayer;
+use crate::dc_blocker::DcBlocker;
+use crate::decimator::Decimator;
+use crate::eq::Equalizer;
+use crate::log::info;
+use crate::midi::BarPosition;
+use crate::midi::Message;
+use crate::midi::Metadata;
+use crate::midi::MidiEvents;
+use crate::note_keys::NoteKeys;
+use crate::options::print_usage;
+use crate::options::Interpolation;
+use crate::options::Options;
+use crate::positions::Positions;
+use crate::ramp::Ramp;
+use crate::resampler::Resampler;
+use crate::sequencer::ProgramOverrides;
+use crate::sequencer::SentMessage;
+use crate::sequencer::Sequencer;
+use crate::theme::Theme;
+use crate::theme::THEMES;
+use crate::wav::WavWriter;
+
 use rustysynth::MidiFile;
-use rustysynth::MidiFileSequencer;
+use rustysynth::MidiFileError;
 use rustysynth::SoundFont;
+use rustysynth::SoundFontError;
 use rustysynth::Synthesizer;
+use rustysynth::SynthesizerError;
 use rustysynth::SynthesizerSettings;
+use sfml::audio::SoundStatus;
 use sfml::audio::SoundStream;
+#[cfg(not(feature = "cpal"))]
 use sfml::audio::SoundStreamPlayer;
+use sfml::graphics::CircleShape;
 use sfml::graphics::Color;
+use sfml::graphics::FloatRect;
+use sfml::graphics::Font;
 use sfml::graphics::PrimitiveType;
+use sfml::graphics::RectangleShape;
 use sfml::graphics::RenderStates;
 use sfml::graphics::RenderTarget;
 use sfml::graphics::RenderWindow;
+use sfml::graphics::Shape;
+use sfml::graphics::Text;
+use sfml::graphics::Texture;
+use sfml::graphics::Transformable;
 use sfml::graphics::Vertex;
+use sfml::graphics::View;
+use sfml::system::Clock;
 use sfml::system::Time;
 use sfml::system::Vector2;
+use sfml::window::mouse;
+use sfml::window::ContextSettings;
 use sfml::window::Event;
+use sfml::window::Key;
 use sfml::window::Style;
+use sfml::window::VideoMode;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fs;
 use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Seek;
+use std::io::Write;
+use std::mem;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
 
 const WAVEFORM_LENGTH: usize = 1024;
 
+// The time constant in seconds of the smoothing applied to the displayed waveform.
+// 24 ms halves the difference to the latest samples every frame at 60 fps.
+const WAVEFORM_SMOOTHING_TIME: f32 = 0.024_f32;
+
+// The height of a full-scale sample above the baseline, as a fraction of the height
+// of the trace, which is 300 pixels in the 1024x768 layout.
+const WAVEFORM_SCALE: f32 = 300_f32 / 768_f32;
+
+// The range and the step of the visual gain of the waveform, which leaves the sound alone.
+const WAVEFORM_GAIN_MIN: f32 = 0.25_f32;
+const WAVEFORM_GAIN_MAX: f32 = 16_f32;
+const WAVEFORM_GAIN_STEP: f32 = 1.25_f32;
+// The auto gain brings the recent peak to this fraction of the half height of the trace,
+// with the peak falling back and the gain following it with these time constants
+// in seconds, so that it doesn't pump between the notes.
+const WAVEFORM_AUTO_FILL: f32 = 0.9_f32;
+const WAVEFORM_PEAK_FALL_TIME: f32 = 2_f32;
+const WAVEFORM_GAIN_SMOOTHING_TIME: f32 = 0.5_f32;
+
+// SFML queues several batches ahead, so they can be long. cpal pulls them
+// as the device needs them, and short ones make the controls respond sooner.
+#[cfg(not(feature = "cpal"))]
+const BATCHES_PER_SECOND: u32 = 20;
+#[cfg(feature = "cpal")]
+const BATCHES_PER_SECOND: u32 = 200;
+
+// The number of most recent samples the spectrum is taken from. A power of two
+// is required by the FFT: 2048 samples at 44.1 kHz give ~21.5 Hz frequency
+// resolution over a ~46 ms window, which is also the span the waveform starts with.
+const FFT_LENGTH: usize = 2048;
+
+// The number of most recent samples shared with the display, which is
+// the widest span the waveform can be zoomed out to, ~186 ms at 44.1 kHz.
+const HISTORY_LENGTH: usize = 8192;
+// The narrowest span, ~2.9 ms at 44.1 kHz, which shows the single cycles of most notes.
+const WAVEFORM_SPAN_MIN: usize = 128;
+
+const SPECTRUM_BAR_COUNT: usize = 64;
+const SPECTRUM_MIN_FREQUENCY: f32 = 20_f32;
+const SPECTRUM_MIN_DB: f32 = -90_f32;
+
+const SCOPE_CIRCLE_SEGMENTS: usize = 128;
+
+const PROGRESS_BAR_HEIGHT: f32 = 8_f32;
+
+// The strip above the progress bar showing where the notes are busiest.
+const DENSITY_STRIP_HEIGHT: f32 = 6_f32;
+const DENSITY_BUCKET_COUNT: usize = 256;
+
+// The range of an 88-key piano and the fraction of the window height it takes.
+const KEYBOARD_LOWEST_KEY: u8 = 21;
+const KEYBOARD_HIGHEST_KEY: u8 = 108;
+const KEYBOARD_HEIGHT: f32 = 0.2_f32;
+
+// How far the Left and Right keys move the playback position in seconds.
+const SEEK_STEP: f64 = 5_f64;
+
+const METER_MIN_DB: f32 = -60_f32;
+const METER_WARNING_DB: f32 = -3_f32;
+const METER_DECAY_DB: f32 = 0.5_f32;
+const METER_WIDTH: f32 = 16_f32;
+
+// How often the levels in the HUD change, so that they can be read. They show
+// the highest peak and the average RMS level since they last changed.
+const LEVEL_READOUT_INTERVAL: Duration = Duration::from_millis(250);
+
+// The correlation gauge follows this fraction of the way to the latest value per frame,
+// and turns red below the warning level, where playing in mono cancels much of the sound.
+const CORRELATION_SMOOTHING: f32 = 0.2_f32;
+const CORRELATION_WARNING: f32 = -0.3_f32;
+const CORRELATION_GAUGE_WIDTH: f32 = 120_f32;
+
+// The number of batches shown on the render load graph, which goes up to the maximum load.
+const RENDER_LOAD_HISTORY: usize = 200;
+const RENDER_LOAD_MAX: f32 = 2_f32;
+const RENDER_LOAD_GRAPH_HEIGHT: f32 = 60_f32;
+
+// The number of the latest messages shown in the event log.
+const EVENT_LOG_LENGTH: usize = 20;
+
+// The channel meters fall by this fraction of full scale per frame once their notes are off.
+const CHANNEL_METER_DECAY: f32 = 0.02_f32;
+const CHANNEL_METER_SIZE: f32 = 20_f32;
+
+// DejaVu Sans Mono, see resources/DejaVuSansMono-LICENSE.txt.
+const HUD_FONT: &[u8] = include_bytes!("../resources/DejaVuSansMono.ttf");
+const HUD_FONT_SIZE: u32 = 14;
+
+const PCM_BUFFER_SIZE: usize = 1 << 16;
+
+// How many of the track names are printed before the rest are summed up.
+const PRINTED_TRACK_NAME_COUNT: usize = 3;
+
+// How often the commands and the playback are checked without the window.
+const NO_WINDOW_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// How often the meters are redrawn in the terminal with --ascii, and their width
+// in characters from METER_MIN_DB to full scale.
+const ASCII_METER_INTERVAL: Duration = Duration::from_millis(200);
+const ASCII_METER_WIDTH: usize = 30;
+
+// How long the audio device has to start playing, and how often that's checked.
+#[cfg(not(feature = "cpal"))]
+const AUDIO_START_TIMEOUT: Duration = Duration::from_secs(2);
+#[cfg(not(feature = "cpal"))]
+const AUDIO_START_POLL_INTERVAL: Duration = Duration::from_millis(10);
+const NO_AUDIO_HINT: &str =
+    "Without an audio device, render to a file with '--render' or to stdout with '--pcm'.";
+
+// The level the peak of each track is brought to by --normalize,
+// and the most a quiet track is raised to get there.
+const NORMALIZE_TARGET_DB: f32 = -1_f32;
+const NORMALIZE_MAX_GAIN: f32 = 16_f32;
+
+// The length in seconds of the fade before the limit set by --duration.
+const DURATION_FADE_LENGTH: f64 = 0.1;
+
+// The length in seconds rendered after the end of the sequence before the stream stops,
+// so that the reverb and the releases of the last notes aren't cut off.
+const END_TAIL_LENGTH: f64 = 2.0;
+
+// A format 0 file with a single empty track, standing in for the MIDI file when playing live.
+const EMPTY_MIDI_FILE: &[u8] =
+    b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x01\xE0MTrk\x00\x00\x00\x04\x00\xFF\x2F\x00";
+
+// The brightness of the waveform at velocity 0, which goes up to full at 127,
+// and the time constant in seconds it follows the average velocity with.
+const VELOCITY_BRIGHTNESS_MIN: f32 = 0.4_f32;
+const VELOCITY_SMOOTHING_TIME: f32 = 0.25_f32;
+
+// How far the background is brightened towards white on each beat.
+const BEAT_PULSE_STRENGTH: f32 = 0.12_f32;
+
+// How long the clip indicator stays lit after the output has clipped.
+const CLIP_INDICATOR_DURATION: Duration = Duration::from_millis(300);
+
+// How long "PANIC" is shown after every channel has been silenced.
+const PANIC_FLASH_DURATION: Duration = Duration::from_millis(500);
+
+// How long a status message replaces the usual title.
+const STATUS_DURATION: Duration = Duration::from_secs(5);
+
+// The shortest time between the seeks while dragging along the progress bar,
+// so that a little of each position is heard and the sequencer isn't reset every frame.
+const SCRUB_SEEK_INTERVAL: Duration = Duration::from_millis(80);
+
+// The level above which the limiter starts to compress the output.
+const LIMITER_THRESHOLD: f32 = 0.8_f32;
+
+// The time in seconds the gain, the pan and the limiter take to move to a new setting.
+const CONTROL_RAMP_TIME: f64 = 0.01;
+
+// The maximum is about the same as the highest level --gain accepts.
+const GAIN_MIN: f32 = 0_f32;
+const GAIN_MAX: f32 = 4_f32;
+const GAIN_STEP: f32 = 0.05_f32;
+
+const GAIN_MIN_DB: f32 = -60_f32;
+const GAIN_MAX_DB: f32 = 12_f32;
+
+// The range of the sample rates accepted by rustysynth.
+const SAMPLE_RATE_MIN: u32 = 16000;
+const SAMPLE_RATE_MAX: u32 = 192000;
+
+// The rate below which the aliasing of the high notes becomes audible.
+const LOW_SAMPLE_RATE: u32 = 22050;
+
+// The range of the maximum polyphony accepted by rustysynth.
+const POLYPHONY_MIN: usize = 8;
+const POLYPHONY_MAX: usize = 256;
+
+const TRANSPOSE_LIMIT: i32 = 24;
+const TUNE_LIMIT_CENTS: i32 = 100;
+
+const EFFECT_SEND_STEP: i32 = 8;
+
+const SPEED_MIN: f32 = 0.25_f32;
+const SPEED_MAX: f32 = 2_f32;
+const SPEED_STEP: f32 = 0.05_f32;
+
+const PAN_STEP: f32 = 0.1_f32;
+
+// The stereo width goes from 0 for mono through 1 for the signal as it is to 2 for wide.
+const WIDTH_MAX: f32 = 2_f32;
+const WIDTH_STEP: f32 = 0.1_f32;
+
+const EQ_LIMIT_DB: f32 = 12_f32;
+const EQ_STEP_DB: f32 = 1_f32;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Visualization {
+    Waveform,
+    Spectrum,
+    Spectrogram,
+    Scope,
+    Keyboard,
+}
+
+impl Visualization {
+    const ALL: [Visualization; 5] = [
+        Visualization::Waveform,
+        Visualization::Spectrum,
+        Visualization::Spectrogram,
+        Visualization::Scope,
+        Visualization::Keyboard,
+    ];
+
+    fn find(name: &str) -> Option<Self> {
+        Visualization::ALL
+            .into_iter()
+            .find(|visualization| visualization.name().eq_ignore_ascii_case(name))
+    }
+
+    fn names() -> String {
+        let names: Vec<&str> = Visualization::ALL
+            .iter()
+            .map(|visualization| visualization.name())
+            .collect();
+        names.join(", ")
+    }
+
+    fn next(self) -> Self {
+        match self {
+            Visualization::Waveform => Visualization::Spectrum,
+            Visualization::Spectrum => Visualization::Spectrogram,
+            Visualization::Spectrogram => Visualization::Scope,
+            Visualization::Scope => Visualization::Keyboard,
+            Visualization::Keyboard => Visualization::Waveform,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Visualization::Waveform => "waveform",
+            Visualization::Spectrum => "spectrum",
+            Visualization::Spectrogram => "spectrogram",
+            Visualization::Scope => "scope",
+            Visualization::Keyboard => "keyboard",
+        }
+    }
+}
+
+// The next file to play along with the gain that normalizes it.
+type QueuedMidiFile = (Arc<MidiFile>, Arc<MidiEvents>, f32);
+
+#[derive(Clone, Copy, PartialEq)]
+enum WaveformStyle {
+    // Filled bars mirrored around the baseline.
+    Bars,
+    // A line through the signed samples, like an oscilloscope.
+    Line,
+}
+
+impl WaveformStyle {
+    fn next(self) -> Self {
+        match self {
+            WaveformStyle::Bars => WaveformStyle::Line,
+            WaveformStyle::Line => WaveformStyle::Bars,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            WaveformStyle::Bars => "bars",
+            WaveformStyle::Line => "line",
+        }
+    }
+}
+
+// The settings applied to the output of the synthesizer.
+#[derive(Clone, Copy)]
+struct Mix {
+    gain: f32,
+    limiter: bool,
+    mono: bool,
+    // The balance from -1 for left only to 1 for right only.
+    pan: f32,
+    // The factor the difference between the channels is scaled by.
+    width: f32,
+    // The gains of the low, mid and high bands in dB.
+    eq_gains: [f32; 3],
+    eq_enabled: bool,
+    dc_block: bool,
+}
+
+// The settings the sequencer follows, which the stream passes on at the start of each batch.
+#[derive(Clone, Copy)]
+struct Controls {
+    sound_font_index: usize,
+    effects: bool,
+    speed: f32,
+    transpose: i32,
+    // In cents, on top of the transpose.
+    fine_tune: i32,
+    // The reverb and chorus send levels for all channels, or None to follow the file.
+    reverb_send: Option<u8>,
+    chorus_send: Option<u8>,
+    // The A and B points of the repeated region in seconds.
+    loop_points: (Option<f64>, Option<f64>),
+    muted: [bool; 16],
+    soloed: [bool; 16],
+    program_overrides: ProgramOverrides,
+}
+
+// The levels of the latest batch.
+#[derive(Clone, Copy)]
+struct Levels {
+    peaks: [f32; 2],
+    // The RMS level of both channels together, in decibels like the peaks.
+    rms: f32,
+    // From -1 for the channels out of phase to 1 for the same signal on both.
+    correlation: f32,
+}
+
+// What the sequencer is playing, for the visualizations.
+struct Activity {
+    // The share of the polyphony taken by the held notes.
+    level: f32,
+    // The running average of the note-on velocities from 0 to 127.
+    average_velocity: f32,
+    // The channel sounding each key, if any.
+    keys: [Option<u8>; 128],
+    // The program of each channel that has played a note.
+    programs: [Option<u8>; 16],
+    // The highest note-on velocity of each channel since the window last took them,
+    // and the number of notes held on each.
+    channel_velocities: [u8; 16],
+    channel_notes: [usize; 16],
+    // Whether each channel plays drums, following the GS and XG messages.
+    drum_channels: [bool; 16],
+}
+
+// How often the stream has clipped or fallen behind.
+struct Stats {
+    // The number of samples clamped to the i16 range so far.
+    clip_count: u64,
+    // The number of batches that took longer to render than to play.
+    underrun_count: u64,
+    // The time each of the latest batches took to render over the time it plays for.
+    render_loads: VecDeque<f32>,
+}
+
+// The latest messages sent to the synthesizer, kept while the event log is shown.
+struct EventLog {
+    shown: bool,
+    messages: VecDeque<String>,
+}
+
+// The state shared between the sound stream and the main loop.
+#[derive(Clone)]
+struct Shared {
+    // The latest samples of the left and right channels.
+    waveform: Arc<Mutex<[Vec<f32>; 2]>>,
+    paused: Arc<Mutex<bool>>,
+    mix: Arc<Mutex<Mix>>,
+    controls: Arc<Mutex<Controls>>,
+    levels: Arc<Mutex<Levels>>,
+    activity: Arc<Mutex<Activity>>,
+    stats: Arc<Mutex<Stats>>,
+    elapsed: Arc<Mutex<u64>>,
+    next_midi_file: Arc<Mutex<Option<QueuedMidiFile>>>,
+    // The pass through the current track and the number of passes to play.
+    loop_pass: Arc<Mutex<(u32, u32)>>,
+    // The messages received from the MIDI input, waiting for the next batch.
+    live_messages: Arc<Mutex<Vec<Message>>>,
+    event_log: Arc<Mutex<EventLog>>,
+    // The WAV file the output is being written to as it's played, if any.
+    recording: Arc<Mutex<Option<WavWriter<BufWriter<File>>>>>,
+    // Set when the window is closed, so that the stream fades out and stops.
+    stopping: Arc<Mutex<bool>>,
+    // Set when the track has ended and its tail has been rendered.
+    finished: Arc<Mutex<bool>>,
+}
+
+impl Shared {
+    fn new(gain: f32, limiter: bool, mono: bool, dc_block: bool, soloed: [bool; 16]) -> Self {
+        let mix = Mix {
+            gain: gain,
+            limiter: limiter,
+            mono: mono,
+            pan: 0_f32,
+            width: 1_f32,
+            eq_gains: [0_f32; 3],
+            eq_enabled: true,
+            dc_block: dc_block,
+        };
+        let controls = Controls {
+            sound_font_index: 0,
+            effects: true,
+            speed: 1_f32,
+            transpose: 0,
+            fine_tune: 0,
+            reverb_send: None,
+            chorus_send: None,
+            loop_points: (None, None),
+            muted: [false; 16],
+            soloed: soloed,
+            program_overrides: [None; 16],
+        };
+        let levels = Levels {
+            peaks: [METER_MIN_DB; 2],
+            rms: METER_MIN_DB,
+            correlation: 1_f32,
+        };
+        let activity = Activity {
+            level: 0_f32,
+            average_velocity: 127_f32,
+            keys: [None; 128],
+            programs: [None; 16],
+            channel_velocities: [0; 16],
+            channel_notes: [0; 16],
+            drum_channels: [false; 16],
+        };
+        let stats = Stats {
+            clip_count: 0,
+            underrun_count: 0,
+            render_loads: VecDeque::new(),
+        };
+        let event_log = EventLog {
+            shown: false,
+            messages: VecDeque::new(),
+        };
+
+        Self {
+            waveform: Arc::new(Mutex::new([
+                vec![0_f32; HISTORY_LENGTH],
+                vec![0_f32; HISTORY_LENGTH],
+            ])),
+            paused: Arc::new(Mutex::new(false)),
+            mix: Arc::new(Mutex::new(mix)),
+            controls: Arc::new(Mutex::new(controls)),
+            levels: Arc::new(Mutex::new(levels)),
+            activity: Arc::new(Mutex::new(activity)),
+            stats: Arc::new(Mutex::new(stats)),
+            elapsed: Arc::new(Mutex::new(0_u64)),
+            next_midi_file: Arc::new(Mutex::new(None)),
+            loop_pass: Arc::new(Mutex::new((1, 1))),
+            live_messages: Arc::new(Mutex::new(Vec::new())),
+            event_log: Arc::new(Mutex::new(event_log)),
+            recording: Arc::new(Mutex::new(None)),
+            stopping: Arc::new(Mutex::new(false)),
+            finished: Arc::new(Mutex::new(false)),
+        }
+    }
+}
+
+// The player the stream is played through, SFML's unless the cpal feature is on.
+#[cfg(not(feature = "cpal"))]
+type Player<'a> = SoundStreamPlayer<'a, MidiMusicStream>;
+#[cfg(feature = "cpal")]
+type Player<'a> = CpalPlayer<MidiMusicStream>;
+
 struct MidiMusicStream {
-    sequencer: MidiFileSequencer,
+    sequencer: Sequencer,
+    sound_fonts: Vec<Arc<SoundFont>>,
+    sound_font_index: usize,
+    effects: bool,
+    midi_file: Arc<MidiFile>,
+    midi_events: Arc<MidiEvents>,
+    play_loop: bool,
+    render_rate: u32,
+    polyphony: usize,
+    // The position in seconds the first track starts at, and how long in seconds
+    // the playback goes on from there before it stops or starts over.
+    start_position: f64,
+    duration: Option<f64>,
+    // The number of times each track is played without looping forever,
+    // and the pass through the current one from 1.
+    loop_count: u32,
+    loop_pass: u32,
+    // The samples played since the start or the last restart at the duration limit,
+    // counted at the device rate over every pass through the track.
+    duration_elapsed: u64,
+    // The gain normalizing the current track, applied on top of the shared gain.
+    track_gain: f32,
+    equalizer: Equalizer,
+    eq_enabled: bool,
+    dc_blocker: DcBlocker,
+    dc_block: bool,
+    // Whether the state drawn in the window is shared, which is skipped without one.
+    visualized: bool,
+    // Whether the stream plays the MIDI input, which keeps it going after the sequence.
+    live: bool,
+    // Whether the stream is rendered to a file or a pipe, where it isn't timed.
+    offline: bool,
+    // The length of the fade-in at the start in samples, and how far it has got.
+    fade_in_length: usize,
+    fade_in_position: usize,
+    // The length of the tail after the end of the sequence in samples,
+    // and how much of it has been rendered.
+    tail_length: usize,
+    tail_position: usize,
+    // Whether the samples are dithered before they're quantized to 16 bits,
+    // and the state of the random numbers for it.
+    dither: bool,
+    dither_state: u32,
+    // The gain and pan being applied, and how much of the mono mix and the limiter
+    // are mixed in, following the shared settings without a click.
+    gain_ramp: Ramp,
+    pan_ramp: Ramp,
+    width_ramp: Ramp,
+    mono_ramp: Ramp,
+    limiter_ramp: Ramp,
+    // The rate the output is played or written at, and the resampler converting to it
+    // when it differs from the rate the synthesizer renders at.
+    device_rate: u32,
+    resampler: Option<Resampler>,
+    // The multiple of the render rate the synthesizer runs at, and the decimator
+    // bringing its samples back down when it's more than one.
+    oversample: u32,
+    decimator: Option<Decimator>,
+    oversampled_left: Vec<f32>,
+    oversampled_right: Vec<f32>,
     left: Vec<f32>,
     right: Vec<f32>,
+    // The samples at the device rate.
+    output_left: Vec<f32>,
+    output_right: Vec<f32>,
     batch: Vec<i16>,
-    mutex: Arc<Mutex<Vec<f32>>>,
+    shared: Shared,
 }
 
 impl MidiMusicStream {
-    const SAMPLE_RATE: u32 = 44100;
     const SAMPLE_MIN: i32 = i16::MIN as i32;
     const SAMPLE_MAX: i32 = i16::MAX as i32;
 
-    fn new(sequencer: MidiFileSequencer, mutex: Arc<Mutex<Vec<f32>>>) -> Self {
-        let batch_length = (MidiMusicStream::SAMPLE_RATE / 20) as usize;
+    // Any nonzero state works for xorshift. A fixed one keeps the renders repeatable.
+    const DITHER_SEED: u32 = 0x2545_F491;
+
+    fn new(
+        sequencer: Sequencer,
+        sound_fonts: Vec<Arc<SoundFont>>,
+        midi_file: Arc<MidiFile>,
+        midi_events: Arc<MidiEvents>,
+        play_loop: bool,
+        sample_rate: u32,
+        shared: Shared,
+    ) -> Self {
+        let batch_length = batch_length(sample_rate, None);
+        let polyphony = sequencer.get_synthesizer().get_maximum_polyphony();
+        let ramp_length = ramp_length(sample_rate);
+        let mix = *shared.mix.lock().unwrap();
+        let mono_mix = if mix.mono { 1_f32 } else { 0_f32 };
+        let limiter_mix = if mix.limiter { 1_f32 } else { 0_f32 };
 
         Self {
             sequencer: sequencer,
+            sound_fonts: sound_fonts,
+            sound_font_index: 0,
+            effects: true,
+            midi_file: midi_file,
+            midi_events: midi_events,
+            play_loop: play_loop,
+            render_rate: sample_rate,
+            polyphony: polyphony,
+            start_position: 0.0,
+            duration: None,
+            loop_count: 1,
+            loop_pass: 1,
+            duration_elapsed: 0,
+            track_gain: 1_f32,
+            equalizer: Equalizer::new(sample_rate),
+            eq_enabled: true,
+            dc_blocker: DcBlocker::new(sample_rate),
+            dc_block: false,
+            visualized: true,
+            live: false,
+            offline: false,
+            fade_in_length: 0,
+            fade_in_position: 0,
+            tail_length: (sample_rate as f64 * END_TAIL_LENGTH) as usize,
+            tail_position: 0,
+            dither: false,
+            dither_state: MidiMusicStream::DITHER_SEED,
+            gain_ramp: Ramp::new(mix.gain, ramp_length),
+            pan_ramp: Ramp::new(mix.pan, ramp_length),
+            width_ramp: Ramp::new(mix.width, ramp_length),
+            mono_ramp: Ramp::new(mono_mix, ramp_length),
+            limiter_ramp: Ramp::new(limiter_mix, ramp_length),
+            device_rate: sample_rate,
+            resampler: None,
+            oversample: 1,
+            decimator: None,
+            oversampled_left: Vec::new(),
+            oversampled_right: Vec::new(),
             left: vec![0_f32; batch_length],
             right: vec![0_f32; batch_length],
+            output_left: Vec::new(),
+            output_right: Vec::new(),
             batch: vec![0; 2 * batch_length],
-            mutex: mutex,
+            shared: shared,
+        }
+    }
+
+    fn create_sequencer(
+        sound_font: &Arc<SoundFont>,
+        sample_rate: u32,
+        polyphony: usize,
+        effects: bool,
+    ) -> Result<Sequencer, SynthesizerError> {
+        let mut settings = SynthesizerSettings::new(sample_rate as i32);
+        settings.maximum_polyphony = polyphony;
+        settings.enable_reverb_and_chorus = effects;
+        let synthesizer = Synthesizer::new(sound_font, &settings)?;
+        Ok(Sequencer::new(synthesizer))
+    }
+
+    // The same as create_sequencer, for the sequencers created after the first one.
+    fn recreate_sequencer(
+        sound_font: &Arc<SoundFont>,
+        sample_rate: u32,
+        polyphony: usize,
+        effects: bool,
+    ) -> Sequencer {
+        // The settings have already been validated when the first sequencer was created.
+        MidiMusicStream::create_sequencer(sound_font, sample_rate, polyphony, effects).unwrap()
+    }
+
+    // Moves the sequencer, which has to be given before the stream is played,
+    // so that the start isn't heard.
+    fn set_start_position(&mut self, position: f64) {
+        self.start_position = position;
+        self.sequencer.seek(position);
+    }
+
+    fn set_loop_count(&mut self, count: u32) {
+        self.loop_count = count;
+    }
+
+    fn set_duration(&mut self, duration: Option<f64>) {
+        self.duration = duration;
+    }
+
+    fn set_track_gain(&mut self, track_gain: f32) {
+        self.track_gain = track_gain;
+    }
+
+    fn set_visualized(&mut self, visualized: bool) {
+        self.visualized = visualized;
+    }
+
+    fn set_live(&mut self, live: bool) {
+        self.live = live;
+    }
+
+    fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
+    fn set_dither(&mut self, dither: bool) {
+        self.dither = dither;
+    }
+
+    // Has to be given before the fade-in, which is counted at the device rate.
+    fn set_device_rate(&mut self, device_rate: u32) {
+        self.device_rate = device_rate;
+        self.resampler = (device_rate != self.render_rate)
+            .then(|| Resampler::new(self.render_rate, device_rate));
+        // The gain and the limiter come after the resampler.
+        let length = ramp_length(device_rate);
+        self.gain_ramp = Ramp::new(self.gain_ramp.get_target(), length);
+        self.limiter_ramp = Ramp::new(self.limiter_ramp.get_target(), length);
+    }
+
+    // Has to be given before the oversampling, whose buffers follow the length of the batch.
+    fn set_batch_length(&mut self, length: usize) {
+        self.left = vec![0_f32; length];
+        self.right = vec![0_f32; length];
+        self.batch = vec![0; 2 * length];
+    }
+
+    // The sequencer has to have been created at the render rate times the factor.
+    fn set_oversample(&mut self, factor: u32) {
+        self.oversample = factor;
+        let length = self.left.len() * factor as usize;
+        self.oversampled_left = vec![0_f32; length];
+        self.oversampled_right = vec![0_f32; length];
+        self.decimator = (factor > 1).then(|| Decimator::new(factor));
+    }
+
+    fn set_fade_in(&mut self, milliseconds: u32) {
+        self.fade_in_length = (self.device_rate as u64 * milliseconds as u64 / 1000) as usize;
+    }
+
+    // Truncates the scaled sample to an integer, or with dither, adds triangular noise
+    // of up to 1 LSB either way and rounds it, which leaves the quantization error
+    // as a steady noise floor instead of distortion following the signal.
+    // Rounding rather than truncating keeps the dither from biasing towards zero.
+    fn quantize(&mut self, value: f32) -> i32 {
+        if !self.dither {
+            return value as i32;
+        }
+        let noise = self.next_random() - self.next_random();
+        (value + noise).round() as i32
+    }
+
+    // A uniform random number from 0 to 1, from a 32-bit xorshift generator.
+    fn next_random(&mut self) -> f32 {
+        let mut x = self.dither_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.dither_state = x;
+        (x >> 8) as f32 / (1 << 24) as f32
+    }
+
+    // Hands the levels, the held notes and the latest samples over to the window.
+    fn share_visualization(&mut self, gain: f32) {
+        let length = self.left.len();
+        let peak_left = self.left.iter().fold(0_f32, |a, &b| a.max(b.abs()));
+        let peak_right = self.right.iter().fold(0_f32, |a, &b| a.max(b.abs()));
+        let mut levels = self.shared.levels.lock().unwrap();
+        levels.peaks = [to_db(gain * peak_left), to_db(gain * peak_right)];
+
+        // The normalized cross-correlation of the batch, which has no phase to tell
+        // when either channel is silent, so the last value is held then.
+        let mut products = 0_f32;
+        let mut energy_left = 0_f32;
+        let mut energy_right = 0_f32;
+        for (left, right) in self.left.iter().zip(&self.right) {
+            products += left * right;
+            energy_left += left * left;
+            energy_right += right * right;
+        }
+        let mean_square = (energy_left + energy_right) / (2 * length) as f32;
+        levels.rms = to_db(gain * mean_square.sqrt());
+        let energy = (energy_left * energy_right).sqrt();
+        if energy > 0_f32 {
+            levels.correlation = (products / energy).clamp(-1_f32, 1_f32);
+        }
+        drop(levels);
+
+        // rustysynth doesn't expose its active voice count,
+        // so the number of held notes is used as the measure of activity.
+        let polyphony = self.sequencer.get_synthesizer().get_maximum_polyphony();
+        let notes = self.sequencer.get_active_note_count();
+        let mut activity = self.shared.activity.lock().unwrap();
+        activity.level = (notes as f32 / polyphony as f32).min(1_f32);
+        if let Some(velocity) = self.sequencer.get_average_velocity() {
+            activity.average_velocity = velocity;
+        }
+        activity.keys = self.sequencer.get_held_keys();
+        activity.programs = self.sequencer.get_programs();
+        activity.drum_channels = self.sequencer.get_drum_channels();
+
+        // Kept until the window takes them, as it may draw a frame only every few batches.
+        let velocities = self.sequencer.take_velocities();
+        for (shared, velocity) in activity.channel_velocities.iter_mut().zip(velocities) {
+            *shared = (*shared).max(velocity);
+        }
+        activity.channel_notes = self.sequencer.get_channel_note_counts();
+        drop(activity);
+
+        let mut event_log = self.shared.event_log.lock().unwrap();
+        for message in self.sequencer.take_sent_messages() {
+            if event_log.messages.len() == EVENT_LOG_LENGTH {
+                event_log.messages.pop_front();
+            }
+            event_log.messages.push_back(format_sent_message(&message));
+        }
+        drop(event_log);
+
+        // Append the newest samples to the shared buffers, dropping the oldest.
+        let mut a = self.shared.waveform.lock().unwrap();
+        let count = length.min(HISTORY_LENGTH);
+        let start = HISTORY_LENGTH - count;
+        for (buffer, source) in a.iter_mut().zip([&self.left, &self.right]) {
+            buffer.copy_within(count.., 0);
+            buffer[start..].copy_from_slice(&source[length - count..length]);
         }
     }
+
+    // Replaces the sequencer with one using another SoundFont or effect setting,
+    // resuming from the current playback position.
+    fn rebuild_sequencer(&mut self, sound_font_index: usize, effects: bool) {
+        let position = self.sequencer.get_position();
+        let speed = self.sequencer.get_speed();
+
+        let mut sequencer = MidiMusicStream::recreate_sequencer(
+            &self.sound_fonts[sound_font_index],
+            self.render_rate * self.oversample,
+            self.polyphony,
+            effects,
+        );
+        sequencer.set_speed(speed);
+        sequencer.play(&self.midi_events, self.play_loop);
+        sequencer.seek(position);
+
+        self.sequencer = sequencer;
+        self.sound_font_index = sound_font_index;
+        self.effects = effects;
+    }
 }
 
 impl SoundStream for MidiMusicStream {
     fn get_data(&mut self) -> (&mut [i16], bool) {
+        if let Some((midi_file, midi_events, track_gain)) =
+            self.shared.next_midi_file.lock().unwrap().take()
+        {
+            self.sequencer.play(&midi_events, self.play_loop);
+            self.midi_file = midi_file;
+            self.midi_events = midi_events;
+            self.track_gain = track_gain;
+            *self.shared.elapsed.lock().unwrap() = 0;
+            self.shared.controls.lock().unwrap().loop_points = (None, None);
+            *self.shared.finished.lock().unwrap() = false;
+            self.start_position = 0.0;
+            self.loop_pass = 1;
+            self.duration_elapsed = 0;
+        }
+
+        let controls = *self.shared.controls.lock().unwrap();
+        if controls.sound_font_index != self.sound_font_index || controls.effects != self.effects {
+            self.rebuild_sequencer(controls.sound_font_index, controls.effects);
+        }
+
+        self.sequencer
+            .set_channel_states(controls.muted, controls.soloed);
+        self.sequencer.set_transpose(controls.transpose);
+        self.sequencer.set_fine_tune(controls.fine_tune);
+        self.sequencer
+            .set_message_logging(self.shared.event_log.lock().unwrap().shown);
+        self.sequencer
+            .set_effect_sends(controls.reverb_send, controls.chorus_send);
+        let region = match controls.loop_points {
+            (Some(a), Some(b)) if a < b => Some((a, b)),
+            _ => None,
+        };
+        self.sequencer.set_loop_region(region);
         self.sequencer
-            .render(&mut self.left[..], &mut self.right[..]);
+            .set_program_overrides(controls.program_overrides);
 
-        let length = self.left.len();
+        // Played even while paused, so that no note-off is lost and leaves a note hanging.
+        for message in self.shared.live_messages.lock().unwrap().drain(..) {
+            self.sequencer.send_message(message);
+        }
+
+        let stopping = *self.shared.stopping.lock().unwrap();
+
+        // While paused, output silence without advancing the sequencer.
+        // The shared waveform is left untouched so that the display freezes.
+        if *self.shared.paused.lock().unwrap() {
+            self.batch.fill(0);
+            return (&mut self.batch[..], !stopping);
+        }
+
+        // The sequencer scales the tempo without resampling, so the pitch is kept.
+        let speed = controls.speed as f64;
+        if speed != self.sequencer.get_speed() {
+            self.sequencer.set_speed(speed);
+        }
+
+        let render_start = (!self.offline).then(Instant::now);
+        match self.decimator.as_mut() {
+            Some(decimator) => {
+                self.sequencer.render(
+                    &mut self.oversampled_left[..],
+                    &mut self.oversampled_right[..],
+                );
+                decimator.process(
+                    &self.oversampled_left,
+                    &self.oversampled_right,
+                    &mut self.left,
+                    &mut self.right,
+                );
+            }
+            None => self
+                .sequencer
+                .render(&mut self.left[..], &mut self.right[..]),
+        }
+
+        // Rendering slower than real time runs the audio device out of samples.
+        if let Some(render_start) = render_start {
+            let batch_duration = self.left.len() as f64 / self.render_rate as f64;
+            let load = render_start.elapsed().as_secs_f64() / batch_duration;
+            let mut stats = self.shared.stats.lock().unwrap();
+            if load > 1.0 {
+                stats.underrun_count += 1;
+            }
+            if stats.render_loads.len() == RENDER_LOAD_HISTORY {
+                stats.render_loads.pop_front();
+            }
+            stats.render_loads.push_back(load as f32);
+        }
+
+        // Each pass but the last starts the sequence over as soon as it has ended,
+        // crossfading like --loop does and leaving the reverb and chorus to ring on.
+        if self.sequencer.end_of_sequence() && self.loop_pass < self.loop_count {
+            self.sequencer.start_over();
+            self.loop_pass += 1;
+        }
+        *self.shared.loop_pass.lock().unwrap() = (self.loop_pass, self.loop_count);
+
+        let mix = *self.shared.mix.lock().unwrap();
+        let dc_block = mix.dc_block;
+        if dc_block && !self.dc_block {
+            self.dc_blocker.reset();
+        }
+        self.dc_block = dc_block;
+        if dc_block {
+            self.dc_blocker
+                .process(&mut self.left[..], &mut self.right[..]);
+        }
+
+        // Constant-power panning, scaled so that the center leaves both channels as they are.
+        self.pan_ramp.set_target(mix.pan);
+        if !self.pan_ramp.is_at(0_f32) {
+            for (left, right) in self.left.iter_mut().zip(self.right.iter_mut()) {
+                let angle = 0.25_f32 * std::f32::consts::PI * (self.pan_ramp.next() + 1_f32);
+                *left *= std::f32::consts::SQRT_2 * angle.cos();
+                *right *= std::f32::consts::SQRT_2 * angle.sin();
+            }
+        }
+
+        // Scales the side, the difference between the channels, against the mid.
+        // Widening also scales both down, by as much as keeps the peak of a source
+        // panned to one side where it was, and the limiter takes care of the rest.
+        self.width_ramp.set_target(mix.width);
+        if !self.width_ramp.is_at(1_f32) {
+            for (left, right) in self.left.iter_mut().zip(self.right.iter_mut()) {
+                let width = self.width_ramp.next();
+                let scale = if width > 1_f32 {
+                    2_f32 / (1_f32 + width)
+                } else {
+                    1_f32
+                };
+                let mid = 0.5_f32 * (*left + *right);
+                let side = 0.5_f32 * width * (*left - *right);
+                *left = scale * (mid + side);
+                *right = scale * (mid - side);
+            }
+        }
+
+        let eq_enabled = mix.eq_enabled;
+        if eq_enabled && !self.eq_enabled {
+            self.equalizer.reset();
+        }
+        self.eq_enabled = eq_enabled;
+        if eq_enabled {
+            self.equalizer.set_gains(mix.eq_gains);
+            self.equalizer
+                .process(&mut self.left[..], &mut self.right[..]);
+        }
+
+        // Both channels carry the same signal, so SFML still gets a stereo stream.
+        self.mono_ramp
+            .set_target(if mix.mono { 1_f32 } else { 0_f32 });
+        if !self.mono_ramp.is_at(0_f32) {
+            for (left, right) in self.left.iter_mut().zip(self.right.iter_mut()) {
+                let mix = self.mono_ramp.next();
+                let mixed = 0.5_f32 * (*left + *right);
+                *left += mix * (mixed - *left);
+                *right += mix * (mixed - *right);
+            }
+        }
+
+        match self.resampler.as_mut() {
+            Some(resampler) => resampler.process(
+                &self.left,
+                &self.right,
+                &mut self.output_left,
+                &mut self.output_right,
+            ),
+            None => {
+                self.output_left.clone_from(&self.left);
+                self.output_right.clone_from(&self.right);
+            }
+        }
+
+        // The track gain only changes between the tracks, so it isn't ramped.
+        let gain = self.track_gain * mix.gain;
+        self.gain_ramp.set_target(mix.gain);
+        self.limiter_ramp
+            .set_target(if mix.limiter { 1_f32 } else { 0_f32 });
+        // Crossfades between the signal and its limited version while the limiter
+        // is switched on or off.
+        let shape = |value: f32, mix: f32| match mix {
+            0_f32 => value,
+            1_f32 => soft_limit(value),
+            _ => value + mix * (soft_limit(value) - value),
+        };
+
+        // The batch has as many samples as the resampler has made this time.
+        let length = self.output_left.len();
+        self.batch.resize(2 * length, 0);
+        let mut clips = 0_u64;
         for t in 0..length {
-            let mut sample_left = (32768_f32 * self.left[t]) as i32;
+            let gain = self.track_gain * self.gain_ramp.next();
+            let mix = self.limiter_ramp.next();
+            let mut sample_left = self.quantize(32768_f32 * shape(gain * self.output_left[t], mix));
             if sample_left < MidiMusicStream::SAMPLE_MIN {
-                sample_left = MidiMusicStream::SAMPLE_MIN
-            };
+                sample_left = MidiMusicStream::SAMPLE_MIN;
+                clips += 1;
+            }
             if sample_left > MidiMusicStream::SAMPLE_MAX {
-                sample_left = MidiMusicStream::SAMPLE_MAX
-            };
+                sample_left = MidiMusicStream::SAMPLE_MAX;
+                clips += 1;
+            }
             let sample_left = sample_left as i16;
 
-            let mut sample_right = (32768_f32 * self.right[t]) as i32;
+            let mut sample_right =
+                self.quantize(32768_f32 * shape(gain * self.output_right[t], mix));
             if sample_right < MidiMusicStream::SAMPLE_MIN {
-                sample_right = MidiMusicStream::SAMPLE_MIN
-            };
+                sample_right = MidiMusicStream::SAMPLE_MIN;
+                clips += 1;
+            }
             if sample_right > MidiMusicStream::SAMPLE_MAX {
-                sample_right = MidiMusicStream::SAMPLE_MAX
-            };
+                sample_right = MidiMusicStream::SAMPLE_MAX;
+                clips += 1;
+            }
             let sample_right = sample_right as i16;
 
             let offset = 2 * t;
@@ -77,119 +1129,3544 @@ impl SoundStream for MidiMusicStream {
             self.batch[offset + 1] = sample_right;
         }
 
-        let batch_length = (MidiMusicStream::SAMPLE_RATE / 20) as usize;
+        self.shared.stats.lock().unwrap().clip_count += clips;
 
-        let mut a = self.mutex.lock().unwrap();
-        for i in 0..WAVEFORM_LENGTH {
-            let p: f64 = (i as f64) / (WAVEFORM_LENGTH as f64) * (batch_length as f64);
-            let j = p as usize;
-            a[i] = self.left[j] + self.right[j];
+        // Ramp the start of the playback up from silence. This happens once only,
+        // so that seeking or changing tracks later doesn't bring it back, except when
+        // starting over at the duration limit.
+        let fade_in_count = (self.fade_in_length - self.fade_in_position).min(length);
+        for t in 0..fade_in_count {
+            let fade = (self.fade_in_position + t) as f32 / self.fade_in_length as f32;
+            let offset = 2 * t;
+            self.batch[offset + 0] = (fade * self.batch[offset + 0] as f32) as i16;
+            self.batch[offset + 1] = (fade * self.batch[offset + 1] as f32) as i16;
+        }
+        self.fade_in_position += fade_in_count;
+
+        // Ramp the last batch down to silence so that stopping doesn't pop.
+        if stopping {
+            for t in 0..length {
+                let fade = 1_f32 - t as f32 / length as f32;
+                let offset = 2 * t;
+                self.batch[offset + 0] = (fade * self.batch[offset + 0] as f32) as i16;
+                self.batch[offset + 1] = (fade * self.batch[offset + 1] as f32) as i16;
+            }
+        }
+
+        // Fade out towards the duration limit, counted in the samples played
+        // whatever the speed and however many times the track has started over.
+        let limit_reached = match self.duration {
+            Some(duration) => {
+                let limit = duration * self.device_rate as f64;
+                let fade_length = DURATION_FADE_LENGTH * self.device_rate as f64;
+                for t in 0..length {
+                    let position = (self.duration_elapsed + t as u64) as f64;
+                    let fade = ((limit - position) / fade_length).clamp(0.0, 1.0);
+                    let offset = 2 * t;
+                    self.batch[offset + 0] = (fade * self.batch[offset + 0] as f64) as i16;
+                    self.batch[offset + 1] = (fade * self.batch[offset + 1] as f64) as i16;
+                }
+                self.duration_elapsed += length as u64;
+                self.duration_elapsed as f64 >= limit
+            }
+            None => false,
+        };
+        // Looping goes back to the start and fades in again, at least as long as
+        // it faded out, instead of jumping back in at full level.
+        if limit_reached && self.play_loop {
+            self.sequencer.seek(self.start_position);
+            self.duration_elapsed = 0;
+            let fade_length = (DURATION_FADE_LENGTH * self.device_rate as f64) as usize;
+            self.fade_in_length = self.fade_in_length.max(fade_length);
+            self.fade_in_position = 0;
         }
 
-        (&mut self.batch[..], true)
+        // The recording gets the samples as they're played, after all the processing.
+        let mut recording = self.shared.recording.lock().unwrap();
+        if let Some(writer) = recording.as_mut() {
+            if let Err(error) = writer.write(&self.batch) {
+                eprintln!("Failed to write the recording: {}", error);
+                *recording = None;
+            }
+        }
+        drop(recording);
+
+        // Past the end, the tail is counted until it has all been rendered.
+        // Seeking back into the sequence starts it over.
+        let ended = !self.live && self.sequencer.end_of_sequence();
+        if ended {
+            self.tail_position += self.left.len();
+        } else {
+            self.tail_position = 0;
+        }
+
+        // The elapsed time follows the position in the song, which differs from
+        // the number of samples rendered when the speed is changed.
+        // It stays at the end while the tail is rendered.
+        let mut position = self.sequencer.get_position();
+        if ended {
+            position = position.min(self.midi_file.get_length());
+        }
+        *self.shared.elapsed.lock().unwrap() = (self.render_rate as f64 * position) as u64;
+
+        if self.visualized {
+            self.share_visualization(gain);
+        }
+
+        // Stop the stream once the sequence and its tail have ended, or at the duration
+        // limit, which has already faded out, so that playback finishes after
+        // the samples already queued have drained.
+        let finished = (ended && self.tail_position >= self.tail_length)
+            || (!self.live && limit_reached && !self.play_loop);
+        if finished {
+            *self.shared.finished.lock().unwrap() = true;
+        }
+        (&mut self.batch[..], !stopping && !finished)
     }
 
-    fn seek(&mut self, _offset: Time) {}
+    fn seek(&mut self, offset: Time) {
+        let sample_rate = self.render_rate as f64;
+        let length = (sample_rate * self.midi_file.get_length()) as u64;
+        let target = ((sample_rate * offset.as_seconds() as f64) as u64).min(length);
+
+        self.sequencer.seek(target as f64 / sample_rate);
+
+        *self.shared.elapsed.lock().unwrap() = target;
+    }
 
     fn channel_count(&self) -> u32 {
         2
     }
 
     fn sample_rate(&self) -> u32 {
-        MidiMusicStream::SAMPLE_RATE
+        self.device_rate
     }
 }
 
-fn print_usage() {
-    eprintln!("Usage: rustysynth-test <soundfont> <midi-file>");
+// The number of samples rendered at a time, from the length in milliseconds if given.
+// Shorter batches make the controls respond sooner, but with more overhead
+// and less time to spare before the device runs out of samples.
+fn batch_length(sample_rate: u32, milliseconds: Option<u32>) -> usize {
+    match milliseconds {
+        Some(milliseconds) => (sample_rate as u64 * milliseconds as u64 / 1000) as usize,
+        None => (sample_rate / BATCHES_PER_SECOND) as usize,
+    }
 }
 
-fn main() {
-    let mut args = std::env::args_os().skip(1);
-    let Some(soundfont_arg) = args.next() else {
-        eprintln!("Missing soundfont.");
-        print_usage();
-        return;
-    };
-    let Some(midi_arg) = args.next() else {
-        eprintln!("Missing path to midi file.");
-        print_usage();
-        return;
-    };
-    let mut window = RenderWindow::new(
-        (1024, 768),
-        "MIDI Music Playback",
-        Style::TITLEBAR | Style::CLOSE,
-        &Default::default(),
-    );
-
-    window.set_framerate_limit(60);
-
-    // Load the SoundFont.
-    let mut sf2 = File::open(soundfont_arg).unwrap();
-    let sound_font = Arc::new(SoundFont::new(&mut sf2).unwrap());
-
-    // Load the MIDI file.
-    let mut mid = File::open(midi_arg).unwrap();
-    let midi_file = Arc::new(MidiFile::new(&mut mid).unwrap());
-
-    // Create the MIDI file sequencer.
-    let settings = SynthesizerSettings::new(44100);
-    let synthesizer = Synthesizer::new(&sound_font, &settings).unwrap();
-    let mut sequencer = MidiFileSequencer::new(synthesizer);
-
-    // Play the MIDI file.
-    sequencer.play(&midi_file, false);
-
-    let wav = vec![0_f32; WAVEFORM_LENGTH];
-    let mutex = Arc::new(Mutex::new(wav));
-    let mutex2 = mutex.clone();
+// The number of samples the controls take to move to a new setting.
+fn ramp_length(sample_rate: u32) -> usize {
+    (sample_rate as f64 * CONTROL_RAMP_TIME) as usize
+}
 
-    // Start the sound stream.
-    let mut stream = MidiMusicStream::new(sequencer, mutex);
-    let mut player = SoundStreamPlayer::new(&mut stream);
-    player.play();
+// Reads the whole file, or stdin when the path is "-".
+fn read_input(path: &OsStr) -> io::Result<Vec<u8>> {
+    if path == "-" {
+        let mut data = Vec::new();
+        io::stdin().lock().read_to_end(&mut data)?;
+        Ok(data)
+    } else {
+        fs::read(path)
+    }
+}
 
-    let mut waveform = vec![0_f32; WAVEFORM_LENGTH];
+fn load_sound_font(path: &OsStr) -> Result<SoundFont, SoundFontError> {
+    let mut sf2 = Cursor::new(read_input(path)?);
+    SoundFont::new(&mut sf2)
+}
 
-    while window.is_open() {
-        while let Some(event) = window.poll_event() {
-            match event {
-                Event::Closed => window.close(),
-                _ => {}
+// Loads all the SoundFonts up front so that switching between them doesn't hitch,
+// along with their names. One that loads but has nothing in it to play is reported,
+// as it would otherwise just stay silent.
+fn load_sound_fonts(paths: &[OsString]) -> Result<(Vec<Arc<SoundFont>>, Vec<String>), String> {
+    let mut sound_fonts = Vec::new();
+    let mut sound_font_names = Vec::new();
+    for path in paths {
+        let sound_font = load_sound_font(path).map_err(|error| {
+            format!(
+                "Failed to load soundfont '{}': {}",
+                path.to_string_lossy(),
+                error
+            )
+        })?;
+        let name = file_name(Path::new(path));
+        let parts = [
+            ("presets", sound_font.get_presets().len()),
+            ("instruments", sound_font.get_instruments().len()),
+            ("samples", sound_font.get_sample_headers().len()),
+            ("sample data", sound_font.get_wave_data().len()),
+        ];
+        for (part, count) in parts {
+            if count == 0 {
+                eprintln!(
+                    "SoundFont '{}' has no {}, so it won't make any sound.",
+                    name, part
+                );
             }
         }
+        sound_fonts.push(Arc::new(sound_font));
+        sound_font_names.push(name);
+    }
+    Ok((sound_fonts, sound_font_names))
+}
 
-        window.clear(Color::rgb(0, 32, 64));
+// What the SoundFont has in it, as in "189 presets, 235 instruments, 864 samples, 30.1 MiB".
+fn sound_font_summary(sound_font: &SoundFont) -> String {
+    format!(
+        "{} presets, {} instruments, {} samples, {}",
+        sound_font.get_presets().len(),
+        sound_font.get_instruments().len(),
+        sound_font.get_sample_headers().len(),
+        format_size(2 * sound_font.get_wave_data().len())
+    )
+}
 
-        {
-            let a = mutex2.lock().unwrap();
-            for i in 0..WAVEFORM_LENGTH {
-                waveform[i] = 0.5_f32 * waveform[i] + 0.5_f32 * a[i];
-            }
+fn format_size(bytes: usize) -> String {
+    let units = ["KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = "bytes";
+    for next in units {
+        if size < 1024.0 {
+            break;
         }
-        draw_waveform(&mut window, &waveform);
-
-        window.display();
+        size /= 1024.0;
+        unit = next;
+    }
+    if unit == "bytes" {
+        format!("{} bytes", bytes)
+    } else {
+        format!("{:.1} {}", size, unit)
     }
 }
 
-fn draw_waveform(window: &mut RenderWindow, data: &[f32]) {
+// When lenient, a file that can't be read as a whole is played without the tracks
+// that can't be read on their own, each with a warning.
+fn load_midi_file(path: &OsStr, lenient: bool) -> Result<(MidiFile, MidiEvents), MidiFileError> {
+    let data = read_input(path)?;
+    match parse_midi_file(&data) {
+        Err(error) if lenient => {
+            let data = drop_unreadable_tracks(path, midi::unwrap_rmid(&data)).ok_or(error)?;
+            parse_midi_file(&data)
+        }
+        result => result,
+    }
+}
+
+// The file without its unreadable tracks, warning about each, or None when there's
+// no header or no track left to play.
+fn drop_unreadable_tracks(path: &OsStr, data: &[u8]) -> Option<Vec<u8>> {
+    let (data, dropped) = midi::drop_unreadable_tracks(data);
+    for track in dropped {
+        eprintln!(
+            "Skipping track {} of {} in '{}', which can't be read: {}",
+            track.index + 1,
+            track.count,
+            path.to_string_lossy(),
+            track.error
+        );
+    }
+    data
+}
+
+fn parse_midi_file(data: &[u8]) -> Result<(MidiFile, MidiEvents), MidiFileError> {
+    let data = midi::unwrap_rmid(data);
+    let midi_file = MidiFile::new(&mut Cursor::new(data))?;
+    Ok((midi_file, MidiEvents::new(data)))
+}
+
+// Lists the MIDI files in the directory in name order, or just the path itself
+// when it isn't a directory.
+fn list_midi_files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        let is_midi = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                ["mid", "midi", "rmi"]
+                    .iter()
+                    .any(|midi| extension.eq_ignore_ascii_case(midi))
+            });
+        if is_midi && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+// Loads the MIDI file and hands it over to the stream, which switches to it
+// on its next batch. Prints the metadata of the file and returns its length
+// in seconds along with its events.
+fn queue_midi_file(
+    shared: &Shared,
+    normalizer: Option<&mut Normalizer>,
+    path: &OsStr,
+    lenient: bool,
+) -> Result<(f64, Arc<MidiEvents>), MidiFileError> {
+    let (midi_file, midi_events) = load_midi_file(path, lenient)?;
+    let length = midi_file.get_length();
+    print_metadata(&midi_events.metadata);
+    let midi_events = Arc::new(midi_events);
+    let track_gain = normalizer.map_or(1_f32, |normalizer| {
+        normalizer.get_gain(Path::new(path), &midi_events)
+    });
+    *shared.next_midi_file.lock().unwrap() =
+        Some((Arc::new(midi_file), Arc::clone(&midi_events), track_gain));
+    Ok((length, midi_events))
+}
+
+fn print_metadata(metadata: &Metadata) {
+    let names = &metadata.track_names;
+    for name in names.iter().take(PRINTED_TRACK_NAME_COUNT) {
+        info!("Track name: {}", name);
+    }
+    if names.len() > PRINTED_TRACK_NAME_COUNT {
+        info!(
+            "... and {} more track names.",
+            names.len() - PRINTED_TRACK_NAME_COUNT
+        );
+    }
+    if let Some(copyright) = &metadata.copyright {
+        info!("Copyright: {}", copyright);
+    }
+    info!("Tempo: {}", metadata.get_summary());
+}
+
+// Prints a summary of each MIDI file to stdout as "key: value" lines,
+// with a blank line between the files, for reading or for other tools.
+// The SoundFonts given are summed up the same way ahead of them.
+fn print_info(
+    path: &Path,
+    sound_fonts: &[Arc<SoundFont>],
+    sound_font_names: &[String],
+    lenient: bool,
+    interpolation: Interpolation,
+) -> ExitCode {
+    let paths = match list_midi_files(path) {
+        Ok(paths) => paths,
+        Err(error) => {
+            eprintln!("Failed to read directory '{}': {}", path.display(), error);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    println!("interpolation: {}", interpolation.name());
+    println!();
+
+    for (sound_font, name) in sound_fonts.iter().zip(sound_font_names) {
+        println!("soundfont: {}", name);
+        println!("soundfont_presets: {}", sound_font.get_presets().len());
+        println!(
+            "soundfont_instruments: {}",
+            sound_font.get_instruments().len()
+        );
+        println!(
+            "soundfont_samples: {}",
+            sound_font.get_sample_headers().len()
+        );
+        println!(
+            "soundfont_sample_bytes: {}",
+            2 * sound_font.get_wave_data().len()
+        );
+        println!();
+    }
+
+    let mut result = ExitCode::SUCCESS;
+    for (index, path) in paths.iter().enumerate() {
+        let (midi_file, midi_events) = match load_midi_file(path.as_os_str(), lenient) {
+            Ok(loaded) => loaded,
+            Err(error) => {
+                eprintln!("Failed to load midi file '{}': {}", path.display(), error);
+                result = ExitCode::FAILURE;
+                continue;
+            }
+        };
+
+        // The drum channels follow the same messages as in the sequencer,
+        // and the instruments are the ones the notes are played with.
+        let mut drums = [false; 16];
+        drums[Sequencer::PERCUSSION_CHANNEL as usize] = true;
+        let mut used = [false; 16];
+        let mut current_programs = [0_u8; 16];
+        // The instruments of each channel that has played notes, in the order they came in.
+        let mut instruments: [Vec<&str>; 16] = Default::default();
+        let mut note_count = 0;
+        for event in &midi_events.events {
+            match event.message {
+                Message::Channel {
+                    channel,
+                    command,
+                    data1,
+                    data2,
+                } => {
+                    let channel = channel as usize;
+                    match command {
+                        0x90 if data2 > 0 => {
+                            used[channel] = true;
+                            note_count += 1;
+                            let program = current_programs[channel];
+                            let name = if drums[channel] {
+                                gm::drum_kit_name(program)
+                            } else {
+                                gm::program_name(program)
+                            };
+                            if !instruments[channel].contains(&name) {
+                                instruments[channel].push(name);
+                            }
+                        }
+                        0xB0 if data1 == 0 => {
+                            if Sequencer::DRUM_BANKS.contains(&data2) {
+                                drums[channel] = true;
+                            } else if channel != Sequencer::PERCUSSION_CHANNEL as usize {
+                                drums[channel] = false;
+                            }
+                        }
+                        0xC0 => current_programs[channel] = data1 & 0x7F,
+                        _ => (),
+                    }
+                }
+                Message::DrumPart { channel, drums: on } => drums[channel as usize] = on,
+            }
+        }
+
+        if index > 0 {
+            println!();
+        }
+        let metadata = &midi_events.metadata;
+        let channels: Vec<String> = (0..16)
+            .filter(|&channel| used[channel])
+            .map(|channel| (channel + 1).to_string())
+            .collect();
+        let (slowest, fastest) = midi_events.get_tempo_range();
+        println!("file: {}", path.display());
+        println!("duration: {:.2}", midi_file.get_length());
+        println!("tracks: {}", midi_events.track_count);
+        println!("notes: {}", note_count);
+        println!("channels: {}", channels.join(" "));
+        println!("tempo: {:.1}", metadata.get_bpm());
+        println!("tempo_range: {:.1} {:.1}", slowest, fastest);
+        if let Some((numerator, denominator)) = metadata.time_signature {
+            println!("time_signature: {}/{}", numerator, denominator);
+        }
+        if let Some(key) = metadata.get_key_name() {
+            println!("key_signature: {}", key);
+        }
+        for name in &metadata.track_names {
+            println!("track_name: {}", name);
+        }
+        if let Some(copyright) = &metadata.copyright {
+            println!("copyright: {}", copyright);
+        }
+        for channel in (0..16).filter(|&channel| used[channel]) {
+            println!(
+                "instruments: {} {}",
+                channel + 1,
+                instruments[channel].join(", ")
+            );
+        }
+    }
+    result
+}
+
+// Finds the gain that brings the peak of each track to the target level
+// by rendering it once in advance, with the first SoundFont and the effects on.
+// The gains are kept by path so that going back to a track doesn't scan it again.
+struct Normalizer {
+    sound_font: Arc<SoundFont>,
+    sample_rate: u32,
+    polyphony: usize,
+    // Whether the offset is taken out first, as it is in the playback.
+    dc_block: bool,
+    gains: HashMap<PathBuf, f32>,
+}
+
+impl Normalizer {
+    const SCAN_LENGTH: usize = 4096;
+
+    fn new(
+        sound_font: &Arc<SoundFont>,
+        sample_rate: u32,
+        polyphony: usize,
+        dc_block: bool,
+    ) -> Self {
+        Self {
+            sound_font: Arc::clone(sound_font),
+            sample_rate: sample_rate,
+            polyphony: polyphony,
+            dc_block: dc_block,
+            gains: HashMap::new(),
+        }
+    }
+
+    fn get_gain(&mut self, path: &Path, midi_events: &Arc<MidiEvents>) -> f32 {
+        if let Some(&gain) = self.gains.get(path) {
+            return gain;
+        }
+
+        let mut sequencer = MidiMusicStream::recreate_sequencer(
+            &self.sound_font,
+            self.sample_rate,
+            self.polyphony,
+            true,
+        );
+        sequencer.play(midi_events, false);
+
+        let mut left = vec![0_f32; Normalizer::SCAN_LENGTH];
+        let mut right = vec![0_f32; Normalizer::SCAN_LENGTH];
+        let mut dc_blocker = self.dc_block.then(|| DcBlocker::new(self.sample_rate));
+        let mut peak = 0_f32;
+        while !sequencer.end_of_sequence() {
+            sequencer.render(&mut left[..], &mut right[..]);
+            if let Some(dc_blocker) = dc_blocker.as_mut() {
+                dc_blocker.process(&mut left[..], &mut right[..]);
+            }
+            peak = left.iter().chain(&right).fold(peak, |a, &b| a.max(b.abs()));
+        }
+
+        // A silent track is left as it is.
+        let gain = if peak > 0_f32 {
+            (10_f32.powf(NORMALIZE_TARGET_DB / 20_f32) / peak).min(NORMALIZE_MAX_GAIN)
+        } else {
+            1_f32
+        };
+        info!(
+            "Normalizing '{}' by {:+.1} dB.",
+            file_name(path),
+            20_f32 * gain.log10()
+        );
+        self.gains.insert(path.to_path_buf(), gain);
+        gain
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn main() -> ExitCode {
+    let options = match Options::parse(std::env::args_os().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            print_usage();
+            return ExitCode::FAILURE;
+        }
+    };
+    log::set_quiet(options.quiet);
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if options.info {
+        // The SoundFonts are optional here, and reported ahead of the MIDI files when given.
+        let (sound_fonts, sound_font_names) = match load_sound_fonts(&options.sound_font_paths) {
+            Ok(loaded) => loaded,
+            Err(message) => {
+                eprintln!("{}", message);
+                return ExitCode::FAILURE;
+            }
+        };
+        return print_info(
+            Path::new(&options.midi_path),
+            &sound_fonts,
+            &sound_font_names,
+            options.lenient,
+            options.interpolation,
+        );
+    }
+
+    // Looping from the config file doesn't apply to rendering, where it would never end,
+    // or to playing live, where there's nothing to loop.
+    let live = options.is_live();
+    // A number of loops given on the command line takes the place of looping forever.
+    let play_loop = options.play_loop
+        || (config.play_loop && !options.is_headless() && !live && options.loop_count.is_none());
+    let sample_rate = options.sample_rate.unwrap_or(config.sample_rate);
+    match options.loop_count {
+        Some(count) => info!("Playing {} times.", count),
+        None => info!("Looping is {}.", on_off(play_loop)),
+    }
+
+    // Checked before anything is loaded, as the synthesizer would only refuse it later.
+    if !(SAMPLE_RATE_MIN..=SAMPLE_RATE_MAX).contains(&sample_rate) {
+        eprintln!(
+            "Sample rate {} Hz is not supported; it must be {} to {} Hz.",
+            sample_rate, SAMPLE_RATE_MIN, SAMPLE_RATE_MAX
+        );
+        return ExitCode::FAILURE;
+    }
+    info!("Render rate is {} Hz.", sample_rate);
+    if sample_rate < LOW_SAMPLE_RATE {
+        eprintln!(
+            "Sample rate {} Hz is low enough for the high notes to alias audibly; \
+             {} Hz or more is recommended.",
+            sample_rate, LOW_SAMPLE_RATE
+        );
+    }
+    let synthesis_rate = sample_rate * options.oversample;
+    if synthesis_rate > SAMPLE_RATE_MAX {
+        eprintln!(
+            "Oversampling {} Hz {} times is not supported; it must come to {} Hz at most.",
+            sample_rate, options.oversample, SAMPLE_RATE_MAX
+        );
+        return ExitCode::FAILURE;
+    }
+    if options.oversample > 1 {
+        info!(
+            "Synthesizing at {} Hz, {} times the render rate.",
+            synthesis_rate, options.oversample
+        );
+    }
+    let batch_length = batch_length(sample_rate, options.latency);
+    info!(
+        "Rendering in batches of {} samples ({:.1} ms).",
+        batch_length,
+        1000_f64 * batch_length as f64 / sample_rate as f64
+    );
+
+    // The device plays at its own rate with cpal, where SFML resamples by itself.
+    #[cfg(not(feature = "cpal"))]
+    let default_device_rate = sample_rate;
+    #[cfg(feature = "cpal")]
+    let default_device_rate = match options.is_headless() {
+        true => sample_rate,
+        false => cpal_player::default_sample_rate().unwrap_or(sample_rate),
+    };
+    let device_rate = options.device_rate.unwrap_or(default_device_rate);
+    if !(SAMPLE_RATE_MIN..=SAMPLE_RATE_MAX).contains(&device_rate) {
+        eprintln!(
+            "Device rate {} Hz is not supported; it must be {} to {} Hz.",
+            device_rate, SAMPLE_RATE_MIN, SAMPLE_RATE_MAX
+        );
+        return ExitCode::FAILURE;
+    }
+    if device_rate == sample_rate {
+        info!("Device rate is {} Hz.", device_rate);
+    } else {
+        info!(
+            "Device rate is {} Hz, resampled from the render rate.",
+            device_rate
+        );
+    }
+
+    let gain = match options.gain_db {
+        Some(db) => {
+            let clamped = db.clamp(GAIN_MIN_DB, GAIN_MAX_DB);
+            if clamped != db {
+                eprintln!(
+                    "Gain {} dB is out of range and has been clamped to {} dB.",
+                    db, clamped
+                );
+            }
+            10_f32.powf(clamped / 20_f32)
+        }
+        None => config.gain.clamp(GAIN_MIN, GAIN_MAX),
+    };
+    info!("Gain is {:.3}.", gain);
+    if let Some(channels) = options.channels {
+        let list: Vec<String> = (0..16)
+            .filter(|&channel| channels[channel])
+            .map(|channel| (channel + 1).to_string())
+            .collect();
+        info!("Soloing channels {}.", list.join(", "));
+    }
+
+    // A misspelled theme in the config file shouldn't keep the program from starting.
+    let mut theme_index = match (&options.theme, &config.theme) {
+        (Some(name), _) => match theme::find_theme(name) {
+            Some(index) => Some(index),
+            None => {
+                eprintln!(
+                    "Unknown theme '{}'; it must be one of {}.",
+                    name,
+                    theme::theme_names()
+                );
+                return ExitCode::FAILURE;
+            }
+        },
+        (None, Some(name)) => {
+            let index = theme::find_theme(name);
+            if index.is_none() {
+                eprintln!(
+                    "Unknown theme '{}' in the config file; it must be one of {}.",
+                    name,
+                    theme::theme_names()
+                );
+            }
+            index
+        }
+        (None, None) => None,
+    };
+
+    // The first of the pair is the one Tab switches.
+    let (visualization, split) = match &options.split {
+        Some((first, second)) => {
+            let mut pair = Vec::new();
+            for name in [first, second] {
+                match Visualization::find(name) {
+                    Some(visualization) => pair.push(visualization),
+                    None => {
+                        eprintln!(
+                            "Unknown visualization '{}'; it must be one of {}.",
+                            name,
+                            Visualization::names()
+                        );
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+            (pair[0], Some(pair[1]))
+        }
+        None => (Visualization::Waveform, None),
+    };
+
+    let polyphony = options.polyphony.clamp(POLYPHONY_MIN, POLYPHONY_MAX);
+    if polyphony != options.polyphony {
+        eprintln!(
+            "Polyphony {} is out of range and has been clamped to {}.",
+            options.polyphony, polyphony
+        );
+    }
+    info!("Maximum polyphony is {}.", polyphony);
+    info!("Interpolation is {}.", options.interpolation.name());
+    if cfg!(feature = "cpal") {
+        info!("Audio backend is cpal.");
+    } else {
+        info!("Audio backend is SFML.");
+    }
+
+    let (sound_fonts, sound_font_names) = match load_sound_fonts(&options.sound_font_paths) {
+        Ok(loaded) => loaded,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if options.list_presets {
+        print_presets(&sound_fonts, &sound_font_names);
+        return ExitCode::SUCCESS;
+    }
+    for (sound_font, name) in sound_fonts.iter().zip(&sound_font_names) {
+        info!(
+            "SoundFont '{}' has {}.",
+            name,
+            sound_font_summary(sound_font)
+        );
+    }
+
+    if let Some((input, output)) = &options.render_dir {
+        let input = Path::new(input);
+        let output = Path::new(output);
+        return render_directory(
+            &sound_fonts,
+            &options,
+            input,
+            output,
+            sample_rate,
+            gain,
+            polyphony,
+        );
+    }
+
+    // Playing live has a single track without a file.
+    let mut playlist = match list_midi_files(Path::new(&options.midi_path)) {
+        _ if live => vec![PathBuf::new()],
+        Ok(playlist) => playlist,
+        Err(error) => {
+            eprintln!(
+                "Failed to read directory '{}': {}",
+                options.midi_path.to_string_lossy(),
+                error
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    if playlist.is_empty() {
+        eprintln!(
+            "No MIDI files found in '{}'.",
+            options.midi_path.to_string_lossy()
+        );
+        return ExitCode::FAILURE;
+    }
+    if playlist.len() > 1 && options.is_headless() {
+        eprintln!("Rendering needs a single MIDI file.");
+        return ExitCode::FAILURE;
+    }
+    let mut track_index = 0;
+
+    // Load the MIDI file.
+    let midi_arg = playlist[0].as_os_str();
+    let loaded = if live {
+        parse_midi_file(EMPTY_MIDI_FILE)
+    } else {
+        load_midi_file(midi_arg, options.lenient)
+    };
+    let (midi_file, midi_events) = match loaded {
+        Ok((midi_file, midi_events)) => (Arc::new(midi_file), Arc::new(midi_events)),
+        Err(error) => {
+            eprintln!(
+                "Failed to load midi file '{}': {}",
+                midi_arg.to_string_lossy(),
+                error
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    // The stream would end at once on an empty file, leaving a render with no samples.
+    if options.is_headless() && midi_file.get_length() <= 0_f64 {
+        eprintln!(
+            "The midi file '{}' is empty, so there's nothing to render.",
+            midi_arg.to_string_lossy()
+        );
+        return ExitCode::FAILURE;
+    }
+
+    let start_position = options.start.unwrap_or(0_f64);
+    if start_position > 0_f64 && start_position >= midi_file.get_length() {
+        eprintln!(
+            "The start {} is past the end of '{}' at {}.",
+            format_time(start_position),
+            midi_arg.to_string_lossy(),
+            format_time(midi_file.get_length())
+        );
+        return ExitCode::FAILURE;
+    }
+
+    // The events of the file being played, for the metadata and the beat.
+    let mut current_events = Arc::clone(&midi_events);
+    let mut density = current_events.get_note_density(midi_file.get_length(), DENSITY_BUCKET_COUNT);
+    if !live {
+        print_metadata(&current_events.metadata);
+    }
+
+    // Create the MIDI file sequencer.
+    let mut sequencer =
+        match MidiMusicStream::create_sequencer(&sound_fonts[0], synthesis_rate, polyphony, true) {
+            Ok(sequencer) => sequencer,
+            Err(error) => {
+                eprintln!("Failed to create synthesizer: {}", error);
+                return ExitCode::FAILURE;
+            }
+        };
+
+    // Play the MIDI file.
+    sequencer.play(&midi_events, play_loop);
+
+    // Scan the whole playlist up front so that moving between the tracks doesn't stall.
+    // The files that fail to load are reported when their turn comes.
+    let mut normalizer = options
+        .normalize
+        .then(|| Normalizer::new(&sound_fonts[0], sample_rate, polyphony, options.dc_block));
+    let mut track_gain = 1_f32;
+    if let Some(normalizer) = normalizer.as_mut() {
+        track_gain = normalizer.get_gain(&playlist[0], &midi_events);
+        for path in &playlist[1..] {
+            if let Ok((_, midi_events)) = load_midi_file(path.as_os_str(), options.lenient) {
+                normalizer.get_gain(path, &Arc::new(midi_events));
+            }
+        }
+    }
+
+    let shared = Shared::new(
+        gain,
+        options.limiter,
+        options.mono,
+        options.dc_block,
+        options.channels.unwrap_or_default(),
+    );
+    let mut total_seconds = midi_file.get_length();
+    let sound_font_count = sound_fonts.len();
+
+    let mut stream = MidiMusicStream::new(
+        sequencer,
+        // Kept here too, for the names of the presets forced on the channels.
+        sound_fonts.clone(),
+        midi_file,
+        midi_events,
+        play_loop,
+        sample_rate,
+        shared.clone(),
+    );
+    stream.set_start_position(start_position);
+    stream.set_duration(options.duration);
+    stream.set_loop_count(options.loop_count.unwrap_or(1));
+    stream.set_track_gain(track_gain);
+    stream.set_visualized(!options.no_window && !options.is_headless());
+    stream.set_offline(options.is_headless());
+    stream.set_live(live);
+    stream.set_device_rate(device_rate);
+    stream.set_batch_length(batch_length);
+    stream.set_oversample(options.oversample);
+    stream.set_fade_in(options.fade_in);
+    stream.set_dither(options.dither);
+
+    if options.is_headless() {
+        // Finish the output cleanly, with a valid header for WAV files, when interrupted by Ctrl-C.
+        let interrupted = handle_interrupts();
+
+        let (result, target) = match &options.render_path {
+            Some(render_path) => (
+                render_to_wav(&mut stream, render_path, &interrupted),
+                format!("'{}'", render_path.to_string_lossy()),
+            ),
+            None => (
+                render_to_pcm(&mut stream, &interrupted),
+                "stdout".to_string(),
+            ),
+        };
+        print_clip_count(&shared);
+        return match result {
+            Ok(()) if interrupted.load(Ordering::SeqCst) => {
+                eprintln!(
+                    "Interrupted; the part rendered so far was written to {}.",
+                    target
+                );
+                ExitCode::FAILURE
+            }
+            Ok(()) => {
+                info!("Rendered to {}.", target);
+                ExitCode::SUCCESS
+            }
+            // The reader at the other end of the pipe has had enough, which is fine.
+            Err(error) if error.kind() == io::ErrorKind::BrokenPipe && options.pcm => {
+                ExitCode::SUCCESS
+            }
+            Err(error) => {
+                eprintln!("Failed to write {}: {}", target, error);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    // Offer to pick the first file up where it was left off.
+    let mut positions = Positions::load().unwrap_or_else(|message| {
+        eprintln!("{}", message);
+        Positions::default()
+    });
+    let mut current_path = playlist[0].clone();
+    if let Some(position) = positions.get(&current_path) {
+        if options.resume {
+            info!("Resuming from {}.", format_time(position));
+            // Looping and the restart at the duration limit go back to where it resumed.
+            stream.set_start_position(position);
+        } else {
+            info!(
+                "Left off at {} last time; pass '--resume' to continue from there.",
+                format_time(position)
+            );
+        }
+    }
+
+    // The port is closed when the connection is dropped at the end.
+    #[cfg(feature = "midi-in")]
+    let _midi_input = match &options.midi_in {
+        Some(port_name) => {
+            match midi_input::connect(port_name, Arc::clone(&shared.live_messages)) {
+                Ok(connection) => Some(connection),
+                Err(message) => {
+                    eprintln!("{}", message);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        None => None,
+    };
+
+    // Start the sound stream.
+    #[cfg(not(feature = "cpal"))]
+    let mut player = SoundStreamPlayer::new(&mut stream);
+    #[cfg(feature = "cpal")]
+    let mut player = match CpalPlayer::new(stream, device_rate) {
+        Ok(player) => player,
+        Err(message) => {
+            eprintln!("{}", message);
+            eprintln!("{}", NO_AUDIO_HINT);
+            return ExitCode::FAILURE;
+        }
+    };
+    player.play();
+
+    // SFML only reports a missing audio device on stderr and then never asks for
+    // more than the first batches, so playback is taken to have started once the
+    // position moves. The window isn't opened until then.
+    #[cfg(not(feature = "cpal"))]
+    {
+        let offset = player.playing_offset();
+        let start = Instant::now();
+        while player.playing_offset() == offset {
+            if start.elapsed() >= AUDIO_START_TIMEOUT {
+                player.stop();
+                eprintln!("The audio device didn't start playing.");
+                eprintln!("{}", NO_AUDIO_HINT);
+                return ExitCode::FAILURE;
+            }
+            thread::sleep(AUDIO_START_POLL_INTERVAL);
+        }
+    }
+
+    if options.no_window || options.ascii {
+        play_without_window(
+            &mut player,
+            &shared,
+            &options,
+            &playlist,
+            normalizer,
+            positions,
+            total_seconds,
+            sample_rate,
+        );
+        return ExitCode::SUCCESS;
+    }
+
+    // The window is only created once everything has loaded successfully.
+    let mut fullscreen = false;
+    let mut windowed_size = (config.window_width, config.window_height);
+    let mut window = create_window(fullscreen, windowed_size);
+
+    // The colors of the config file apply while no theme has been picked.
+    let [r, g, b] = config.background_color;
+    let config_theme = Theme {
+        name: "config",
+        background: Color::rgb(r, g, b),
+        waveform: config.waveform_color.map(|[r, g, b]| Color::rgb(r, g, b)),
+        accent: theme::DEFAULT_ACCENT,
+    };
+    let margin = config.waveform_margin as f32;
+
+    let mut waveform = [vec![0_f32; WAVEFORM_LENGTH], vec![0_f32; WAVEFORM_LENGTH]];
+    let mut mixed = vec![0_f32; WAVEFORM_LENGTH];
+    let mut samples = vec![0_f32; FFT_LENGTH];
+    let mut stereo_samples = [vec![0_f32; FFT_LENGTH], vec![0_f32; FFT_LENGTH]];
+    let mut visualization = visualization;
+    let mut waveform_style = WaveformStyle::Bars;
+    let mut waveform_span = FFT_LENGTH;
+    let mut stereo = false;
+    // The visualizations keep showing the samples they had when frozen.
+    let mut frozen = false;
+    let mut frame_clock = Clock::start();
+    let mut screenshot_requested = false;
+    let mut fft_window = fft::WindowCache::new(fft::Window::Hann);
+    let mut spectrogram: VecDeque<[f32; SPECTRUM_BAR_COUNT]> = VecDeque::new();
+    let mut meters = [METER_MIN_DB; 2];
+    let mut correlation = 1_f32;
+    let mut channel_meters = [0_f32; 16];
+    let mut show_channel_meters = false;
+    let mut show_render_loads = false;
+    let mut show_event_log = false;
+    let mut status: Option<(String, Instant)> = None;
+    let mut path_receiver: Option<Receiver<String>> = None;
+    let mut preset_receiver: Option<Receiver<String>> = None;
+    let mut next_track: Option<usize> = None;
+    let mut show_hud = true;
+    // The letters and the number row play notes instead of what they'd do otherwise.
+    let mut note_keys = options.keyboard.then(|| {
+        info!("Playing notes from Z upwards, with 1-0 picking programs 1 to 10.");
+        NoteKeys::new()
+    });
+    let mut show_help = false;
+    let mut beat_pulse = true;
+    let mut velocity_brightness = true;
+    // The visual gain of the waveform, and whether it follows the recent peak.
+    let mut waveform_gain = 1_f32;
+    let mut auto_waveform_gain = false;
+    let mut waveform_peak = 0_f32;
+    let mut brightness = 1_f32;
+    let mut level_readout = (METER_MIN_DB, METER_MIN_DB);
+    let mut level_time = Instant::now();
+    let mut level_peak = METER_MIN_DB;
+    let mut level_power = 0_f32;
+    let mut level_frames = 0;
+    let mut clip_count = 0_u64;
+    let mut clip_time: Option<Instant> = None;
+    let mut panic_time: Option<Instant> = None;
+    // The position under the cursor while dragging along the progress bar,
+    // and the time and position of the last seek made for it.
+    let mut scrub: Option<f32> = None;
+    let mut scrub_seek: Option<(Instant, f32)> = None;
+
+    // SAFETY: The font data is static, so it outlives the font.
+    let font = unsafe { Font::from_memory(HUD_FONT) };
+    if font.is_none() {
+        eprintln!("Failed to load the HUD font.");
+    }
+
+    while window.is_open() {
+        // The last track closes the window as soon as its tail has been rendered,
+        // which has rung out by then, without waiting for the queue to drain.
+        if *shared.finished.lock().unwrap() && track_index + 1 == playlist.len() {
+            positions.set(&current_path, None);
+            window.close();
+            continue;
+        }
+
+        // The stream stops by itself when the sequence has ended
+        // or after fading out once the window has been asked to close.
+        // In the former case, the playlist moves on to the next track if any.
+        if player.status() == SoundStatus::STOPPED {
+            let stopping = *shared.stopping.lock().unwrap();
+            if !stopping {
+                positions.set(&current_path, None);
+            }
+            if !stopping && track_index + 1 < playlist.len() {
+                next_track = Some(track_index + 1);
+            } else {
+                window.close();
+            }
+        }
+
+        while let Some(event) = window.poll_event() {
+            match event {
+                Event::Closed => *shared.stopping.lock().unwrap() = true,
+                Event::Resized { width, height } => {
+                    // Keep one unit per pixel instead of stretching the original view.
+                    let area = FloatRect::new(0_f32, 0_f32, width as f32, height as f32);
+                    window.set_view(&View::from_rect(&area));
+                }
+                Event::MouseButtonPressed {
+                    button: mouse::Button::Left,
+                    x,
+                    y,
+                } if y as f32 >= window.size().y as f32 - PROGRESS_BAR_HEIGHT => {
+                    scrub = Some((x as f32 / window.size().x as f32).clamp(0_f32, 1_f32));
+                    scrub_seek = None;
+                }
+                Event::MouseMoved { x, .. } if scrub.is_some() => {
+                    scrub = Some((x as f32 / window.size().x as f32).clamp(0_f32, 1_f32));
+                }
+                // Land where the button is released, which the throttled seeks may have skipped.
+                Event::MouseButtonReleased {
+                    button: mouse::Button::Left,
+                    ..
+                } => {
+                    if let Some(fraction) = scrub.take() {
+                        if scrub_seek.is_none_or(|(_, seeked)| seeked != fraction) {
+                            player
+                                .set_playing_offset(Time::seconds(fraction * total_seconds as f32));
+                        }
+                    }
+                }
+                Event::KeyPressed {
+                    code: Key::Escape, ..
+                } if show_help => show_help = false,
+                Event::KeyPressed { code, shift, .. } => {
+                    if let Some(note_keys) = note_keys.as_mut() {
+                        if note_keys.press(code, &mut shared.live_messages.lock().unwrap()) {
+                            continue;
+                        }
+                    }
+                    let Some(action) = bindings::find_action(code, shift) else {
+                        continue;
+                    };
+                    if apply_playback_action(&shared, action, sound_font_count, sample_rate) {
+                        continue;
+                    }
+                    match action {
+                        Action::NextVisualization => visualization = visualization.next(),
+                        // SFML doesn't report files dropped onto the window,
+                        // so the path of the next MIDI file is read from stdin instead.
+                        Action::OpenFile
+                            if path_receiver.is_none() && preset_receiver.is_none() =>
+                        {
+                            eprintln!("Enter the path to a MIDI file:");
+                            path_receiver = Some(read_line_in_background());
+                        }
+                        Action::ForcePreset
+                            if path_receiver.is_none() && preset_receiver.is_none() =>
+                        {
+                            eprintln!(
+                                "Enter a channel, bank and program to force a preset \
+                                 on the channel, or just the channel to release it:"
+                            );
+                            preset_receiver = Some(read_line_in_background());
+                        }
+                        Action::OpenFile | Action::ForcePreset => (),
+                        Action::ToggleStereo => stereo = !stereo,
+                        Action::ToggleFreeze => frozen = !frozen,
+                        Action::ToggleHud => show_hud = !show_hud,
+                        Action::ToggleChannelMeters => show_channel_meters = !show_channel_meters,
+                        Action::ToggleBeatPulse => beat_pulse = !beat_pulse,
+                        Action::ToggleVelocityBrightness => {
+                            velocity_brightness = !velocity_brightness
+                        }
+                        Action::ToggleRenderLoads => show_render_loads = !show_render_loads,
+                        Action::ToggleEventLog => {
+                            show_event_log = !show_event_log;
+                            let mut event_log = shared.event_log.lock().unwrap();
+                            event_log.shown = show_event_log;
+                            event_log.messages.clear();
+                        }
+                        // Setting the gain by hand takes it over from the auto gain.
+                        Action::WaveformGainUp => {
+                            auto_waveform_gain = false;
+                            waveform_gain =
+                                (waveform_gain * WAVEFORM_GAIN_STEP).min(WAVEFORM_GAIN_MAX);
+                        }
+                        Action::WaveformGainDown => {
+                            auto_waveform_gain = false;
+                            waveform_gain =
+                                (waveform_gain / WAVEFORM_GAIN_STEP).max(WAVEFORM_GAIN_MIN);
+                        }
+                        Action::ToggleAutoWaveformGain => auto_waveform_gain = !auto_waveform_gain,
+                        Action::NextWaveformStyle => waveform_style = waveform_style.next(),
+                        // Zoom the time axis of the waveform in and out by factors of two.
+                        Action::ZoomIn => {
+                            waveform_span = (waveform_span / 2).max(WAVEFORM_SPAN_MIN)
+                        }
+                        Action::ZoomOut => waveform_span = (waveform_span * 2).min(HISTORY_LENGTH),
+                        Action::NextTheme => {
+                            let index = theme_index.map_or(0, |index| (index + 1) % THEMES.len());
+                            theme_index = Some(index);
+                            info!("Theme is {}.", THEMES[index].name);
+                        }
+                        Action::NextFftWindow => {
+                            fft_window.set_window(fft_window.get_window().next());
+                        }
+                        Action::SeekBackward | Action::SeekForward => {
+                            let step = if action == Action::SeekBackward {
+                                -SEEK_STEP
+                            } else {
+                                SEEK_STEP
+                            };
+                            let position =
+                                *shared.elapsed.lock().unwrap() as f64 / sample_rate as f64;
+                            let target = (position + step).clamp(0_f64, total_seconds);
+                            player.set_playing_offset(Time::seconds(target as f32));
+                        }
+                        Action::NextTrack if track_index + 1 < playlist.len() => {
+                            next_track = Some(track_index + 1);
+                        }
+                        Action::PreviousTrack if track_index > 0 => {
+                            next_track = Some(track_index - 1);
+                        }
+                        Action::NextTrack | Action::PreviousTrack => (),
+                        Action::Screenshot => screenshot_requested = true,
+                        Action::ToggleRecording => {
+                            let message = report(toggle_recording(&shared, device_rate));
+                            status = Some((message, Instant::now()));
+                        }
+                        // SFML can only change the style by recreating the window, which
+                        // leaves the sound stream alone. The visualizations follow its size.
+                        Action::ToggleFullscreen => {
+                            if !fullscreen {
+                                let size = window.size();
+                                windowed_size = (size.x, size.y);
+                            }
+                            fullscreen = !fullscreen;
+                            window = create_window(fullscreen, windowed_size);
+                        }
+                        Action::Panic => {
+                            silence_channels(&mut shared.live_messages.lock().unwrap());
+                            panic_time = Some(Instant::now());
+                        }
+                        Action::ToggleHelp => show_help = !show_help,
+                        // The rest change the playback and have been applied above.
+                        _ => (),
+                    }
+                }
+                Event::KeyReleased { code, .. } => {
+                    if let Some(note_keys) = note_keys.as_mut() {
+                        note_keys.release(code, &mut shared.live_messages.lock().unwrap());
+                    }
+                }
+                Event::LostFocus => {
+                    if let Some(note_keys) = note_keys.as_mut() {
+                        note_keys.release_all(&mut shared.live_messages.lock().unwrap());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(fraction) = scrub {
+            let due = scrub_seek.is_none_or(|(time, seeked)| {
+                seeked != fraction && time.elapsed() >= SCRUB_SEEK_INTERVAL
+            });
+            if due {
+                player.set_playing_offset(Time::seconds(fraction * total_seconds as f32));
+                scrub_seek = Some((Instant::now(), fraction));
+            }
+        }
+
+        // The track that has just been handed over to the stream, with its length and events.
+        let mut queued = None;
+
+        // A file typed on stdin joins the playlist once it has loaded, unless it's already
+        // there, so that the title and the next and previous tracks follow it.
+        if let Some(line) = path_receiver.as_ref().and_then(|r| r.try_recv().ok()) {
+            path_receiver = None;
+            let path = PathBuf::from(line.trim());
+            if !path.as_os_str().is_empty() {
+                match queue_midi_file(
+                    &shared,
+                    normalizer.as_mut(),
+                    path.as_os_str(),
+                    options.lenient,
+                ) {
+                    Ok(loaded) => {
+                        let index = match playlist.iter().position(|track| *track == path) {
+                            Some(index) => index,
+                            None => {
+                                playlist.push(path.clone());
+                                playlist.len() - 1
+                            }
+                        };
+                        status = Some((format!("Playing '{}'", path.display()), Instant::now()));
+                        queued = Some((index, loaded));
+                    }
+                    Err(error) => {
+                        let message =
+                            format!("Failed to load midi file '{}': {}", path.display(), error);
+                        eprintln!("{}", message);
+                        status = Some((message, Instant::now()));
+                    }
+                }
+            }
+        }
+
+        if let Some(index) = next_track.take() {
+            // A track that fails to load is still made current, so that
+            // the playlist moves past it instead of retrying it.
+            track_index = index;
+            let path = &playlist[index];
+            current_path = path.clone();
+            match queue_midi_file(
+                &shared,
+                normalizer.as_mut(),
+                path.as_os_str(),
+                options.lenient,
+            ) {
+                Ok(loaded) => queued = Some((index, loaded)),
+                Err(error) => {
+                    let message = format!(
+                        "Failed to load midi file '{}': {}",
+                        path.to_string_lossy(),
+                        error
+                    );
+                    eprintln!("{}", message);
+                    status = Some((message, Instant::now()));
+                }
+            }
+        }
+
+        // Either way, the track becomes the current one, and a stream that has stopped
+        // at the end of the previous one is started again.
+        if let Some((index, (length, events))) = queued {
+            track_index = index;
+            current_path = playlist[index].clone();
+            total_seconds = length;
+            current_events = events;
+            density = current_events.get_note_density(length, DENSITY_BUCKET_COUNT);
+            if player.status() == SoundStatus::STOPPED {
+                player.play();
+            }
+        }
+
+        if let Some(line) = preset_receiver.as_ref().and_then(|r| r.try_recv().ok()) {
+            preset_receiver = None;
+            let sound_font = &sound_fonts[shared.controls.lock().unwrap().sound_font_index];
+            let result = match parse_program_override(&line) {
+                Ok((channel, Some((bank, program)))) => {
+                    shared.controls.lock().unwrap().program_overrides[channel] =
+                        Some((bank, program));
+                    Ok(format!(
+                        "Channel {} is forced to {}",
+                        channel + 1,
+                        preset_name(sound_font, bank, program)
+                    ))
+                }
+                Ok((channel, None)) => {
+                    shared.controls.lock().unwrap().program_overrides[channel] = None;
+                    Ok(format!("Channel {} follows the file again", channel + 1))
+                }
+                Err(message) => Err(message),
+            };
+            let message = report(result);
+            status = Some((message, Instant::now()));
+        }
+
+        let elapsed_seconds = *shared.elapsed.lock().unwrap() as f64 / sample_rate as f64;
+        let controls = *shared.controls.lock().unwrap();
+        let mix = *shared.mix.lock().unwrap();
+        let sound_font_index = controls.sound_font_index;
+        let effects = controls.effects;
+        let transpose = controls.transpose;
+        let fine_tune = controls.fine_tune;
+        let speed = controls.speed;
+        let gain = mix.gain;
+        let pan = mix.pan;
+        let width = mix.width;
+        let track = format!(
+            "track {}/{}: {}",
+            track_index + 1,
+            playlist.len(),
+            file_name(&playlist[track_index])
+        );
+        let mut title = format!(
+            "MIDI Music Playback — {} / {} — {:.2}× — transpose {:+} — {} — effects {}",
+            format_time(elapsed_seconds),
+            format_length(total_seconds),
+            speed,
+            transpose,
+            sound_font_names[sound_font_index],
+            on_off(effects)
+        );
+        if playlist.len() > 1 {
+            title.push_str(&format!(" — {}", track));
+        }
+        let is_spectral = |visualization| {
+            matches!(
+                visualization,
+                Visualization::Spectrum | Visualization::Spectrogram
+            )
+        };
+        if is_spectral(visualization) || split.is_some_and(is_spectral) {
+            title.push_str(&format!(
+                " — {}-point FFT ({:.1} Hz, {:.0} ms, {} window)",
+                FFT_LENGTH,
+                sample_rate as f32 / FFT_LENGTH as f32,
+                1000_f32 * FFT_LENGTH as f32 / sample_rate as f32,
+                fft_window.get_window().name()
+            ));
+        }
+        if let Some((message, time)) = &status {
+            if time.elapsed() < STATUS_DURATION {
+                title = format!("MIDI Music Playback — {}", message);
+            }
+        }
+        window.set_title(&title);
+
+        let theme = theme_index.map_or(&config_theme, |index| &THEMES[index]);
+        let background = theme.background;
+
+        // Blend by the time since the last frame, so that the smoothing
+        // looks the same whatever the frame rate.
+        let dt = frame_clock.restart().as_seconds();
+        let blend = 1_f32 - (-dt / WAVEFORM_SMOOTHING_TIME).exp();
+
+        // The waveform is dimmed for soft playing and lit up fully for loud playing.
+        let activity = shared.activity.lock().unwrap();
+        let brightness_target = if velocity_brightness {
+            let velocity = activity.average_velocity / 127_f32;
+            VELOCITY_BRIGHTNESS_MIN + (1_f32 - VELOCITY_BRIGHTNESS_MIN) * velocity
+        } else {
+            1_f32
+        };
+        brightness +=
+            (1_f32 - (-dt / VELOCITY_SMOOTHING_TIME).exp()) * (brightness_target - brightness);
+        let waveform_color = theme
+            .waveform
+            .unwrap_or_else(|| activity_color(activity.level));
+        let keys = activity.keys;
+        let programs = activity.programs;
+        let drum_channels = activity.drum_channels;
+        drop(activity);
+        let waveform_color = dim(waveform_color, brightness);
+
+        // Flash on each beat and fade out before the next.
+        if beat_pulse {
+            let phase = current_events.get_beat(elapsed_seconds).fract() as f32;
+            let pulse = BEAT_PULSE_STRENGTH * (1_f32 - phase).powi(3);
+            window.clear(brighten(background, pulse));
+        } else {
+            window.clear(background);
+        }
+
+        if !frozen {
+            // The waveform spans the latest samples it's been zoomed to,
+            // and the spectrum and the scope always take the latest FFT_LENGTH.
+            let a = shared.waveform.lock().unwrap();
+            let span_start = HISTORY_LENGTH - waveform_span;
+            for ch in 0..2 {
+                for i in 0..WAVEFORM_LENGTH {
+                    let j = span_start + i * waveform_span / WAVEFORM_LENGTH;
+                    waveform[ch][i] += blend * (a[ch][j] - waveform[ch][i]);
+                }
+            }
+            let fft_start = HISTORY_LENGTH - FFT_LENGTH;
+            for i in 0..FFT_LENGTH {
+                samples[i] = a[0][fft_start + i] + a[1][fft_start + i];
+            }
+            stereo_samples[0].copy_from_slice(&a[0][fft_start..]);
+            stereo_samples[1].copy_from_slice(&a[1][fft_start..]);
+        }
+
+        // The peak is of the channels shown apart or of their sum, as they're drawn.
+        if auto_waveform_gain {
+            let peak = (0..WAVEFORM_LENGTH)
+                .map(|i| {
+                    let [left, right] = [waveform[0][i], waveform[1][i]];
+                    if stereo {
+                        left.abs().max(right.abs())
+                    } else {
+                        (left + right).abs()
+                    }
+                })
+                .fold(0_f32, f32::max);
+            waveform_peak = peak.max(waveform_peak * (-dt / WAVEFORM_PEAK_FALL_TIME).exp());
+            let target = if waveform_peak > 0_f32 {
+                0.5_f32 * WAVEFORM_AUTO_FILL / (WAVEFORM_SCALE * waveform_peak)
+            } else {
+                WAVEFORM_GAIN_MAX
+            };
+            let target = target.clamp(WAVEFORM_GAIN_MIN, WAVEFORM_GAIN_MAX);
+            waveform_gain +=
+                (1_f32 - (-dt / WAVEFORM_GAIN_SMOOTHING_TIME).exp()) * (target - waveform_gain);
+        }
+
+        let coefficients = fft_window.get_coefficients(FFT_LENGTH);
+        let bands = spectrum_bands(&samples, coefficients, sample_rate);
+
+        // One column per frame and per pixel, so the history spans the window.
+        if !*shared.paused.lock().unwrap() && !frozen {
+            spectrogram.push_back(bands);
+        }
+        while spectrogram.len() > window.size().x as usize {
+            spectrogram.pop_front();
+        }
+
+        // Each visualization draws into its own area, with a view that keeps it from
+        // spilling into the other's.
+        let size = window.size();
+        let full = FloatRect::new(0_f32, 0_f32, size.x as f32, size.y as f32);
+        let panes = match split {
+            Some(second) => {
+                let half = 0.5_f32 * full.width;
+                vec![
+                    (
+                        visualization,
+                        FloatRect::new(0_f32, 0_f32, half, full.height),
+                    ),
+                    (
+                        second,
+                        FloatRect::new(half, 0_f32, full.width - half, full.height),
+                    ),
+                ]
+            }
+            None => vec![(visualization, full)],
+        };
+        for (visualization, area) in panes {
+            let mut view = View::from_rect(&area);
+            view.set_viewport(&FloatRect::new(
+                area.left / full.width,
+                area.top / full.height,
+                area.width / full.width,
+                area.height / full.height,
+            ));
+            window.set_view(&view);
+
+            let col = waveform_color;
+            let gain = waveform_gain;
+            // The band of the area the trace takes, from the given fraction of its height
+            // and for the given fraction of it.
+            let band = |top: f32, height: f32| {
+                FloatRect::new(
+                    area.left,
+                    area.top + top * area.height,
+                    area.width,
+                    height * area.height,
+                )
+            };
+            match visualization {
+                Visualization::Waveform => {
+                    if stereo {
+                        // Left on the top half and right on the bottom half.
+                        let style = waveform_style;
+                        let top = band(0_f32, 0.5_f32);
+                        draw_waveform(&mut window, top, &waveform[0], style, col, margin, gain);
+                        let bottom = band(0.5_f32, 0.5_f32);
+                        draw_waveform(&mut window, bottom, &waveform[1], style, col, margin, gain);
+                    } else {
+                        for i in 0..WAVEFORM_LENGTH {
+                            mixed[i] = waveform[0][i] + waveform[1][i];
+                        }
+                        let style = waveform_style;
+                        draw_waveform(&mut window, area, &mixed, style, col, margin, gain);
+                    }
+                    draw_margins(&mut window, area, margin);
+                }
+                Visualization::Spectrum => draw_spectrum(&mut window, area, &bands),
+                Visualization::Spectrogram => draw_spectrogram(&mut window, area, &spectrogram),
+                Visualization::Scope => {
+                    draw_scope(&mut window, area, &stereo_samples[0], &stereo_samples[1])
+                }
+                Visualization::Keyboard => {
+                    // The mixed trace fills the space above the keyboard.
+                    for i in 0..WAVEFORM_LENGTH {
+                        mixed[i] = waveform[0][i] + waveform[1][i];
+                    }
+                    let space = band(0_f32, 1_f32 - KEYBOARD_HEIGHT);
+                    let style = waveform_style;
+                    draw_waveform(&mut window, space, &mixed, style, col, margin, gain);
+                    draw_keyboard(&mut window, area, &keys);
+                }
+            }
+        }
+        window.set_view(&View::from_rect(&full));
+
+        let progress = if total_seconds > 0_f64 {
+            (elapsed_seconds / total_seconds) as f32
+        } else {
+            0_f32
+        };
+        let (a, b) = controls.loop_points;
+        let markers: Vec<f32> = [a, b]
+            .into_iter()
+            .flatten()
+            .filter(|_| total_seconds > 0_f64)
+            .map(|position| (position / total_seconds) as f32)
+            .collect();
+        draw_density(&mut window, &density, theme.accent);
+        draw_progress_bar(&mut window, progress, &markers, theme.accent);
+        if let (Some(fraction), Some(font)) = (scrub, font.as_deref()) {
+            let time = format_time(fraction as f64 * total_seconds);
+            draw_tooltip(&mut window, font, &time, fraction);
+        }
+
+        // Hold the peaks and let them fall slowly so that the meters don't flicker.
+        let levels = *shared.levels.lock().unwrap();
+        let peaks = levels.peaks;
+        for ch in 0..2 {
+            meters[ch] = peaks[ch].max(meters[ch] - METER_DECAY_DB);
+        }
+        draw_meters(&mut window, &meters);
+
+        level_peak = level_peak.max(peaks[0].max(peaks[1]));
+        level_power += 10_f32.powf(levels.rms / 10_f32);
+        level_frames += 1;
+        if level_time.elapsed() >= LEVEL_READOUT_INTERVAL {
+            let rms = to_db((level_power / level_frames as f32).sqrt());
+            level_readout = (level_peak, rms);
+            level_time = Instant::now();
+            level_peak = METER_MIN_DB;
+            level_power = 0_f32;
+            level_frames = 0;
+        }
+
+        if show_render_loads {
+            draw_render_loads(&mut window, &shared.stats.lock().unwrap().render_loads);
+        }
+        if let Some(font) = font.as_ref().filter(|_| show_event_log) {
+            draw_event_log(
+                &mut window,
+                font,
+                &shared.event_log.lock().unwrap().messages,
+            );
+        }
+
+        correlation += CORRELATION_SMOOTHING * (levels.correlation - correlation);
+        draw_correlation(&mut window, correlation);
+
+        // Each channel jumps to the velocity of its loudest new note, and holds
+        // while it has notes on, since rustysynth doesn't tell the level of its voices.
+        let mut activity = shared.activity.lock().unwrap();
+        let velocities = mem::take(&mut activity.channel_velocities);
+        let notes = activity.channel_notes;
+        drop(activity);
+        for ch in 0..16 {
+            let decay = if notes[ch] > 0 {
+                0_f32
+            } else {
+                CHANNEL_METER_DECAY
+            };
+            let level = velocities[ch] as f32 / 127_f32;
+            channel_meters[ch] = level.max(channel_meters[ch] - decay);
+        }
+        if show_channel_meters {
+            draw_channel_meters(&mut window, &channel_meters, &drum_channels);
+        }
+
+        let muted = controls.muted;
+        let soloed = controls.soloed;
+        draw_channel_states(&mut window, &muted, &soloed);
+
+        let stats = shared.stats.lock().unwrap();
+        let count = stats.clip_count;
+        let underruns = stats.underrun_count;
+        let render_load = stats.render_loads.back().copied();
+        drop(stats);
+        if count > clip_count {
+            clip_count = count;
+            clip_time = Some(Instant::now());
+        }
+        if clip_time.is_some_and(|time| time.elapsed() < CLIP_INDICATOR_DURATION) {
+            draw_clip_indicator(&mut window, font.as_deref());
+        }
+        if shared.recording.lock().unwrap().is_some() {
+            draw_recording_indicator(&mut window, font.as_deref());
+        }
+        if panic_time.is_some_and(|time| time.elapsed() < PANIC_FLASH_DURATION) {
+            draw_panic_flash(&mut window, font.as_deref());
+        }
+
+        if let Some(font) = font.as_ref().filter(|_| show_hud) {
+            let mut hud = format!(
+                "{} / {}  bar {}\ntempo      {}\ngain       {:.2}\nspeed      {:.2}×\npan        {:+.1}\nwidth      {:.1}\ntranspose  {:+}\ntune       {:+} cents\nsoundfont  {}\neffects    {}\nreverb     {}\nchorus     {}\neq         {}\nwindow     {}",
+                format_time(elapsed_seconds),
+                format_length(total_seconds),
+                format_bar_position(current_events.get_bar_position(elapsed_seconds)),
+                current_events.metadata.get_summary(),
+                gain,
+                speed,
+                pan,
+                width,
+                transpose,
+                fine_tune,
+                sound_font_names[sound_font_index],
+                on_off(effects),
+                send_level(controls.reverb_send),
+                send_level(controls.chorus_send),
+                eq_settings(mix.eq_gains, mix.eq_enabled),
+                fft_window.get_window().name()
+            );
+            hud.push_str(&format!(
+                "\nspan       {:.1} ms",
+                1000_f32 * waveform_span as f32 / sample_rate as f32
+            ));
+            hud.push_str(&format!(
+                "\nwave gain  ×{:.2}{}",
+                waveform_gain,
+                if auto_waveform_gain { " (auto)" } else { "" }
+            ));
+            hud.push_str(&format!(
+                "\nlevel      Peak {:.1} dB  RMS {:.1} dB",
+                level_readout.0, level_readout.1
+            ));
+            if frozen {
+                hud.push_str("\nFROZEN");
+            }
+            let (loop_pass, loop_count) = *shared.loop_pass.lock().unwrap();
+            if loop_count > 1 {
+                hud.push_str(&format!("\nloop       {}/{}", loop_pass, loop_count));
+            }
+            if playlist.len() > 1 {
+                hud.push_str(&format!("\n{}", track));
+            }
+            for (channel, program) in programs.iter().enumerate() {
+                let Some(program) = *program else {
+                    continue;
+                };
+                let name = if drum_channels[channel] {
+                    gm::drum_kit_name(program)
+                } else {
+                    gm::program_name(program)
+                };
+                hud.push_str(&format!("\nch {:<2}      {}", channel + 1, name));
+            }
+            if underruns > 0 {
+                hud.push_str(&format!(
+                    "\nunderruns  {} (try lowering the polyphony)",
+                    underruns
+                ));
+            }
+            if let Some((message, time)) = &status {
+                if time.elapsed() < STATUS_DURATION {
+                    hud.push_str(&format!("\n{}", message));
+                }
+            }
+            draw_hud(&mut window, font, &hud);
+        }
+
+        // Each row of the help shows the current value of what its keys change.
+        if let Some(font) = font.as_ref().filter(|_| show_help) {
+            let channel_list = |states: [bool; 16]| {
+                let channels: Vec<String> = (0..16)
+                    .filter(|&channel| states[channel])
+                    .map(|channel| (channel + 1).to_string())
+                    .collect();
+                if channels.is_empty() {
+                    "none".to_string()
+                } else {
+                    channels.join(" ")
+                }
+            };
+            let mut help = String::new();
+            for binding in BINDINGS {
+                let value = match binding.keys[0].2 {
+                    Action::Pause => on_off(*shared.paused.lock().unwrap()).to_string(),
+                    Action::GainUp | Action::GainDown => format!("{:.2}", gain),
+                    Action::SpeedDown | Action::SpeedUp => format!("{:.2}×", speed),
+                    Action::PanLeft | Action::PanRight => format!("{:+.1}", pan),
+                    Action::WidthDown | Action::WidthUp => format!("{:.1}", width),
+                    Action::SeekBackward | Action::SeekForward => format_time(elapsed_seconds),
+                    Action::TransposeUp | Action::TransposeDown => format!("{:+}", transpose),
+                    Action::TuneUp | Action::TuneDown => format!("{:+} cents", fine_tune),
+                    Action::SetLoopStart | Action::SetLoopEnd | Action::ClearLoop => {
+                        let (a, b) = controls.loop_points;
+                        let point = |point: Option<f64>| point.map_or("-".to_string(), format_time);
+                        format!("{} to {}", point(a), point(b))
+                    }
+                    Action::NextTrack | Action::PreviousTrack => {
+                        format!("{}/{}", track_index + 1, playlist.len())
+                    }
+                    Action::NextSoundFont => sound_font_names[sound_font_index].clone(),
+                    Action::ForcePreset => {
+                        channel_list(controls.program_overrides.map(|preset| preset.is_some()))
+                    }
+                    Action::Mute(_) => channel_list(muted),
+                    Action::Solo(_) => channel_list(soloed),
+                    Action::ToggleEffects => on_off(effects).to_string(),
+                    Action::ReverbDown | Action::ReverbUp => send_level(controls.reverb_send),
+                    Action::ChorusDown | Action::ChorusUp => send_level(controls.chorus_send),
+                    Action::ToggleEq => on_off(mix.eq_enabled).to_string(),
+                    Action::ToggleDcBlock => on_off(mix.dc_block).to_string(),
+                    Action::EqUp(_) | Action::EqDown(_) => {
+                        let [low, mid, high] = mix.eq_gains;
+                        format!("{:+} {:+} {:+} dB", low, mid, high)
+                    }
+                    Action::ToggleMono => on_off(mix.mono).to_string(),
+                    Action::NextVisualization => visualization.name().to_string(),
+                    Action::NextWaveformStyle => waveform_style.name().to_string(),
+                    Action::ToggleStereo => on_off(stereo).to_string(),
+                    Action::ToggleFreeze => on_off(frozen).to_string(),
+                    Action::ZoomIn | Action::ZoomOut => format!(
+                        "{:.1} ms",
+                        1000_f32 * waveform_span as f32 / sample_rate as f32
+                    ),
+                    Action::NextFftWindow => fft_window.get_window().name().to_string(),
+                    Action::ToggleChannelMeters => on_off(show_channel_meters).to_string(),
+                    Action::ToggleBeatPulse => on_off(beat_pulse).to_string(),
+                    Action::ToggleVelocityBrightness => on_off(velocity_brightness).to_string(),
+                    Action::WaveformGainUp | Action::WaveformGainDown => {
+                        format!("×{:.2}", waveform_gain)
+                    }
+                    Action::ToggleAutoWaveformGain => on_off(auto_waveform_gain).to_string(),
+                    Action::ToggleEventLog => on_off(show_event_log).to_string(),
+                    Action::ToggleRenderLoads => render_load
+                        .map_or("-".to_string(), |load| format!("{:.0}%", 100_f32 * load)),
+                    Action::NextTheme => theme.name.to_string(),
+                    Action::ToggleHud => on_off(show_hud).to_string(),
+                    Action::ToggleFullscreen => on_off(fullscreen).to_string(),
+                    Action::ToggleRecording => {
+                        on_off(shared.recording.lock().unwrap().is_some()).to_string()
+                    }
+                    Action::OpenFile | Action::Screenshot | Action::Panic | Action::ToggleHelp => {
+                        String::new()
+                    }
+                };
+                help.push_str(&format!(
+                    "{:<14}{:<50}{}\n",
+                    binding.label, binding.description, value
+                ));
+            }
+            draw_help(&mut window, font, help.trim_end());
+        }
+
+        // Capture the frame before it's displayed, while the drawing is still in the back buffer.
+        if screenshot_requested {
+            screenshot_requested = false;
+            let message = report(save_screenshot(&window).map(|path| format!("Saved '{}'", path)));
+            status = Some((message, Instant::now()));
+        }
+
+        window.display();
+    }
+
+    player.stop();
+    if shared.recording.lock().unwrap().is_some() {
+        report(toggle_recording(&shared, device_rate));
+    }
+    save_positions(&mut positions, &current_path, &shared, sample_rate);
+    // The theme last used is kept for the next time, whether it came from the option or the key.
+    let saved_theme = config.theme.as_deref().and_then(theme::find_theme);
+    if let Some(index) = theme_index.filter(|&index| Some(index) != saved_theme) {
+        if let Err(message) = config.save_theme(THEMES[index].name) {
+            eprintln!("{}", message);
+        }
+    }
+    print_clip_count(&shared);
+    print_underrun_count(&shared);
+
+    ExitCode::SUCCESS
+}
+
+// Plays through the playlist from the first track without a window, taking commands from
+// stdin and drawing the meters in the terminal with --ascii, until it ends or is stopped.
+#[allow(clippy::too_many_arguments)]
+fn play_without_window(
+    player: &mut Player,
+    shared: &Shared,
+    options: &Options,
+    playlist: &[PathBuf],
+    mut normalizer: Option<Normalizer>,
+    mut positions: Positions,
+    length: f64,
+    sample_rate: u32,
+) {
+    let mut track_index = 0;
+    let mut current_path = playlist[0].clone();
+
+    // Fade out and stop as closing the window does, on Ctrl-C or the quit command.
+    let interrupted = handle_interrupts();
+    // There are no commands to read once a file has been read from stdin.
+    let commands = (!options.reads_stdin()).then(read_lines_in_background);
+    if commands.is_some() {
+        eprintln!("Enter 'p' to pause or resume, or 'q' to quit.");
+    } else {
+        eprintln!("Only Ctrl-C stops the playback, as stdin was read for a file.");
+    }
+    // The highest peaks since the meters were last drawn.
+    let mut ascii_peaks = [METER_MIN_DB; 2];
+    let mut ascii_time = Instant::now();
+    loop {
+        if player.status() == SoundStatus::STOPPED {
+            if *shared.stopping.lock().unwrap() {
+                break;
+            }
+            positions.set(&current_path, None);
+            if track_index + 1 == playlist.len() {
+                break;
+            }
+            // A track that fails to load is passed over on the next round.
+            track_index += 1;
+            let path = &playlist[track_index];
+            current_path = path.clone();
+            match queue_midi_file(
+                shared,
+                normalizer.as_mut(),
+                path.as_os_str(),
+                options.lenient,
+            ) {
+                Ok(_) => {
+                    info!("Playing '{}'.", file_name(path));
+                    player.play();
+                }
+                Err(error) => {
+                    eprintln!("Failed to load midi file '{}': {}", path.display(), error)
+                }
+            }
+        }
+
+        if interrupted.load(Ordering::SeqCst) {
+            *shared.stopping.lock().unwrap() = true;
+        }
+        while let Some(line) = commands.as_ref().and_then(|c| c.try_recv().ok()) {
+            match line.trim() {
+                "p" => {
+                    let mut p = shared.paused.lock().unwrap();
+                    *p = !*p;
+                }
+                "q" => *shared.stopping.lock().unwrap() = true,
+                "" => (),
+                command => eprintln!("Unknown command '{}'.", command),
+            }
+        }
+
+        if options.ascii {
+            let peaks = shared.levels.lock().unwrap().peaks;
+            for ch in 0..2 {
+                ascii_peaks[ch] = ascii_peaks[ch].max(peaks[ch]);
+            }
+            if ascii_time.elapsed() >= ASCII_METER_INTERVAL {
+                let elapsed = *shared.elapsed.lock().unwrap() as f64 / sample_rate as f64;
+                draw_ascii_meters(elapsed, length, ascii_peaks);
+                ascii_peaks = [METER_MIN_DB; 2];
+                ascii_time = Instant::now();
+            }
+        }
+
+        thread::sleep(NO_WINDOW_POLL_INTERVAL);
+    }
+    if options.ascii {
+        println!();
+    }
+    player.stop();
+    save_positions(&mut positions, &current_path, shared, sample_rate);
+    print_clip_count(shared);
+    print_underrun_count(shared);
+}
+
+// Creates either a fullscreen window at the desktop resolution
+// or a resizable one with the given size.
+fn create_window(fullscreen: bool, windowed_size: (u32, u32)) -> RenderWindow {
+    // Multisampling smooths the line drawn by the line style of the waveform.
+    let settings = ContextSettings {
+        antialiasing_level: 8,
+        ..Default::default()
+    };
+    let mut window = if fullscreen {
+        RenderWindow::new(
+            VideoMode::desktop_mode(),
+            "MIDI Music Playback",
+            Style::FULLSCREEN,
+            &settings,
+        )
+    } else {
+        RenderWindow::new(
+            windowed_size,
+            "MIDI Music Playback",
+            Style::TITLEBAR | Style::CLOSE | Style::RESIZE,
+            &settings,
+        )
+    };
+
+    window.set_framerate_limit(60);
+    window
+}
+
+// Renders the whole sequence into a WAV file without opening the window,
+// using the same processing as the playback.
+// The output depends on nothing but the files and the options, so rendering
+// again gives the same bytes: rustysynth uses no random numbers, the dither
+// starts from a fixed seed, the batches have a fixed length, and neither
+// the clock nor the window state is involved.
+// Builds for other targets or with other compiler settings may still round
+// the floating-point math differently.
+fn render_to_wav(
+    stream: &mut MidiMusicStream,
+    path: &OsStr,
+    interrupted: &AtomicBool,
+) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    write_wav(stream, file, interrupted)
+}
+
+// Renders the stream to the end into a WAV stream of any kind.
+fn write_wav<W: Write + Seek>(
+    stream: &mut MidiMusicStream,
+    output: W,
+    interrupted: &AtomicBool,
+) -> io::Result<()> {
+    let mut writer = WavWriter::new(output, stream.device_rate)?;
+    loop {
+        let (batch, keep_playing) = stream.get_data();
+        writer.write(batch)?;
+        if !keep_playing || interrupted.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+    writer.finalize()
+}
+
+// Renders each MIDI file in the input directory to a WAV file of the same name
+// in the output directory, with the settings of a single render. The files are
+// shared out among as many threads as there are cores, all with the same SoundFonts.
+// A file that fails is reported and skipped, and counts against the exit code.
+fn render_directory(
+    sound_fonts: &[Arc<SoundFont>],
+    options: &Options,
+    input: &Path,
+    output: &Path,
+    sample_rate: u32,
+    gain: f32,
+    polyphony: usize,
+) -> ExitCode {
+    let paths = match list_midi_files(input) {
+        Ok(paths) if input.is_dir() => paths,
+        Ok(_) => {
+            eprintln!("'{}' is not a directory.", input.display());
+            return ExitCode::FAILURE;
+        }
+        Err(error) => {
+            eprintln!("Failed to read directory '{}': {}", input.display(), error);
+            return ExitCode::FAILURE;
+        }
+    };
+    if paths.is_empty() {
+        eprintln!("No MIDI files found in '{}'.", input.display());
+        return ExitCode::FAILURE;
+    }
+    // The files are written at the render rate unless told otherwise, as there's no device.
+    let device_rate = options.device_rate.unwrap_or(sample_rate);
+    if let Err(error) = fs::create_dir_all(output) {
+        eprintln!("Failed to create '{}': {}", output.display(), error);
+        return ExitCode::FAILURE;
+    }
+
+    let render = |midi_path: &Path, wav_path: &Path, interrupted: &AtomicBool| {
+        let (midi_file, midi_events) = load_midi_file(midi_path.as_os_str(), options.lenient)
+            .map_err(|error| format!("Failed to load midi file: {}", error))?;
+        if midi_file.get_length() <= 0_f64 {
+            return Err("The midi file is empty.".to_string());
+        }
+        let midi_events = Arc::new(midi_events);
+        let mut sequencer = MidiMusicStream::create_sequencer(
+            &sound_fonts[0],
+            sample_rate * options.oversample,
+            polyphony,
+            true,
+        )
+        .map_err(|error| format!("Failed to create synthesizer: {}", error))?;
+        sequencer.play(&midi_events, false);
+
+        let shared = Shared::new(
+            gain,
+            options.limiter,
+            options.mono,
+            options.dc_block,
+            options.channels.unwrap_or_default(),
+        );
+        let mut stream = MidiMusicStream::new(
+            sequencer,
+            sound_fonts.to_vec(),
+            Arc::new(midi_file),
+            midi_events,
+            false,
+            sample_rate,
+            shared.clone(),
+        );
+        stream.set_duration(options.duration);
+        stream.set_loop_count(options.loop_count.unwrap_or(1));
+        stream.set_visualized(false);
+        stream.set_offline(true);
+        stream.set_device_rate(device_rate);
+        stream.set_batch_length(batch_length(sample_rate, options.latency));
+        stream.set_oversample(options.oversample);
+        stream.set_fade_in(options.fade_in);
+        stream.set_dither(options.dither);
+
+        render_to_wav(&mut stream, wav_path.as_os_str(), interrupted)
+            .map_err(|error| format!("Failed to write '{}': {}", wav_path.display(), error))?;
+        let clips = shared.stats.lock().unwrap().clip_count;
+        Ok(clips)
+    };
+
+    let wav_paths = wav_paths(&paths, output);
+    let interrupted = handle_interrupts();
+    let next = AtomicUsize::new(0);
+    let finished = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+    let thread_count = thread::available_parallelism()
+        .map_or(1, |count| count.get())
+        .min(paths.len());
+    info!(
+        "Rendering {} files on {} threads.",
+        paths.len(),
+        thread_count
+    );
+
+    thread::scope(|scope| {
+        for _ in 0..thread_count {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                if index >= paths.len() || interrupted.load(Ordering::SeqCst) {
+                    break;
+                }
+                let midi_path = &paths[index];
+                let result = wav_paths[index]
+                    .as_ref()
+                    .map_err(Clone::clone)
+                    .and_then(|wav_path| {
+                        render(midi_path, wav_path, &interrupted).map(|clips| (wav_path, clips))
+                    });
+                let count = finished.fetch_add(1, Ordering::SeqCst) + 1;
+                match result {
+                    Ok((wav_path, clips)) => info!(
+                        "[{}/{}] Rendered '{}' to '{}' with {} samples clipped.",
+                        count,
+                        paths.len(),
+                        file_name(midi_path),
+                        wav_path.display(),
+                        clips
+                    ),
+                    Err(message) => {
+                        failed.fetch_add(1, Ordering::SeqCst);
+                        eprintln!(
+                            "[{}/{}] Failed to render '{}': {}",
+                            count,
+                            paths.len(),
+                            file_name(midi_path),
+                            message
+                        );
+                    }
+                }
+            });
+        }
+    });
+
+    let failed = failed.load(Ordering::SeqCst);
+    if interrupted.load(Ordering::SeqCst) {
+        eprintln!("Interrupted; the file being rendered at the time is incomplete.");
+        ExitCode::FAILURE
+    } else if failed > 0 {
+        eprintln!("{} of {} files failed to render.", failed, paths.len());
+        ExitCode::FAILURE
+    } else {
+        info!("Rendered {} files to '{}'.", paths.len(), output.display());
+        ExitCode::SUCCESS
+    }
+}
+
+// The WAV file in the output directory each MIDI file is rendered to, named after it.
+// The files that would end up with the same name, like 'a.mid' and 'a.midi', keep
+// their own extension in it as well, so that they don't overwrite each other.
+fn wav_paths(paths: &[PathBuf], output: &Path) -> Vec<Result<PathBuf, String>> {
+    // Compared without the case, for the file systems that ignore it.
+    let stem = |path: &PathBuf| path.file_stem().map(|stem| stem.to_ascii_lowercase());
+    paths
+        .iter()
+        .map(|path| {
+            let Some(name) = path.file_name() else {
+                return Err("The file has no name to give the WAV file.".to_string());
+            };
+            let shared = paths
+                .iter()
+                .filter(|other| stem(other) == stem(path))
+                .count()
+                > 1;
+            let mut name = match shared {
+                true => name.to_os_string(),
+                false => Path::new(name).with_extension("").into_os_string(),
+            };
+            name.push(".wav");
+            Ok(output.join(name))
+        })
+        .collect()
+}
+
+// Remembers the position of the file being played when the playback was stopped
+// before its end, and writes out the positions if anything has changed.
+fn save_positions(
+    positions: &mut Positions,
+    current_path: &Path,
+    shared: &Shared,
+    sample_rate: u32,
+) {
+    if *shared.stopping.lock().unwrap() {
+        let elapsed = *shared.elapsed.lock().unwrap() as f64 / sample_rate as f64;
+        positions.set(current_path, Some(elapsed));
+    }
+    if let Err(message) = positions.save() {
+        eprintln!("{}", message);
+    }
+}
+
+fn print_underrun_count(shared: &Shared) {
+    info!(
+        "{} batches took longer to render than to play.",
+        shared.stats.lock().unwrap().underrun_count
+    );
+}
+
+fn print_clip_count(shared: &Shared) {
+    info!(
+        "{} samples were clipped.",
+        shared.stats.lock().unwrap().clip_count
+    );
+}
+
+// Writes the whole sequence to stdout as headerless interleaved
+// 16-bit little-endian stereo samples, for piping into other tools.
+fn render_to_pcm(stream: &mut MidiMusicStream, interrupted: &AtomicBool) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut writer = BufWriter::with_capacity(PCM_BUFFER_SIZE, stdout.lock());
+    let mut bytes = Vec::new();
+    loop {
+        let (batch, keep_playing) = stream.get_data();
+        bytes.clear();
+        for sample in batch.iter() {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        writer.write_all(&bytes)?;
+        if !keep_playing || interrupted.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+    writer.flush()
+}
+
+// Sets a flag when Ctrl-C is pressed instead of terminating the process.
+fn handle_interrupts() -> Arc<AtomicBool> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_flag = Arc::clone(&interrupted);
+    if let Err(error) = ctrlc::set_handler(move || handler_flag.store(true, Ordering::SeqCst)) {
+        eprintln!("Failed to set the Ctrl-C handler: {}", error);
+    }
+    interrupted
+}
+
+// Forwards the lines typed into stdin until it's closed.
+fn read_lines_in_background() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+// Reads "<channel> <bank> <program>", with the channel from 1 to 16 as it's shown
+// and the bank and program as they're listed by '--list-presets', or "<channel>" alone.
+fn parse_program_override(line: &str) -> Result<(usize, Option<(u8, u8)>), String> {
+    let invalid = || format!("Invalid preset '{}'.", line.trim());
+    let values: Vec<u8> = line
+        .split_whitespace()
+        .map(|value| value.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let (channel, preset) = match values[..] {
+        [channel] => (channel, None),
+        [channel, bank, program] if bank <= 128 && program <= 127 => {
+            (channel, Some((bank, program)))
+        }
+        _ => return Err(invalid()),
+    };
+    if !(1..=16).contains(&channel) {
+        return Err(format!(
+            "Channel {} doesn't exist; it must be 1 to 16.",
+            channel
+        ));
+    }
+    Ok((channel as usize - 1, preset))
+}
+
+// The name of the preset with the given bank and program, which the synthesizer
+// replaces with one from the GM set when the SoundFont doesn't have it.
+fn preset_name(sound_font: &SoundFont, bank: u8, program: u8) -> String {
+    sound_font
+        .get_presets()
+        .iter()
+        .find(|preset| {
+            preset.get_bank_number() == bank as i32 && preset.get_patch_number() == program as i32
+        })
+        .map_or_else(
+            || format!("{}:{} (missing)", bank, program),
+            |preset| format!("{}:{}:{}", bank, program, preset.get_name()),
+        )
+}
+
+// Prints the presets of each SoundFont to stdout as "bank:program:name" lines,
+// in the order of bank and program, after a line with the name of the SoundFont.
+fn print_presets(sound_fonts: &[Arc<SoundFont>], sound_font_names: &[String]) {
+    for (index, (sound_font, name)) in sound_fonts.iter().zip(sound_font_names).enumerate() {
+        if index > 0 {
+            println!();
+        }
+        println!("# {}", name);
+        println!("# {}", sound_font_summary(sound_font));
+        let mut presets: Vec<_> = sound_font.get_presets().iter().collect();
+        presets.sort_by_key(|preset| (preset.get_bank_number(), preset.get_patch_number()));
+        for preset in presets {
+            println!(
+                "{}:{}:{}",
+                preset.get_bank_number(),
+                preset.get_patch_number(),
+                preset.get_name()
+            );
+        }
+    }
+}
+
+// Reads one line from stdin without blocking the window.
+fn read_line_in_background() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line).is_ok() {
+            let _ = sender.send(line);
+        }
+    });
+    receiver
+}
+
+// Leaves the levels up to the threshold as they are and bends the ones above it
+// smoothly towards full scale with tanh, so that they no longer reach it.
+fn soft_limit(value: f32) -> f32 {
+    let magnitude = value.abs();
+    if magnitude <= LIMITER_THRESHOLD {
+        return value;
+    }
+    let headroom = 1_f32 - LIMITER_THRESHOLD;
+    let limited =
+        LIMITER_THRESHOLD + headroom * ((magnitude - LIMITER_THRESHOLD) / headroom).tanh();
+    limited.copysign(value)
+}
+
+// Converts a linear amplitude into dBFS, with silence reading as the bottom of the meters.
+fn to_db(value: f32) -> f32 {
+    if value > 0_f32 {
+        (20_f32 * value.log10()).max(METER_MIN_DB)
+    } else {
+        METER_MIN_DB
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+// Saves what has been drawn to the window as a PNG named after the current time.
+fn save_screenshot(window: &RenderWindow) -> Result<String, String> {
+    let size = window.size();
+    let mut texture = Texture::new().ok_or("Failed to create a texture for the screenshot.")?;
+    if !texture.create(size.x, size.y) {
+        return Err("Failed to create a texture for the screenshot.".to_string());
+    }
+    // SAFETY: The texture has just been created with the size of the window.
+    unsafe { texture.update_from_render_window(window, 0, 0) };
+    let image = texture
+        .copy_to_image()
+        .ok_or("Failed to read back the screenshot.")?;
+
+    let path = format!("waveform-{}.png", timestamp());
+    if image.save_to_file(&path) {
+        Ok(path)
+    } else {
+        Err(format!("Failed to save '{}'", path))
+    }
+}
+
+// Redraws the line of the meters in place, with the ANSI code clearing the previous one.
+fn draw_ascii_meters(elapsed: f64, length: f64, peaks: [f32; 2]) {
+    let meter = |db: f32| {
+        let fraction = ((db - METER_MIN_DB) / -METER_MIN_DB).clamp(0_f32, 1_f32);
+        let filled = (fraction * ASCII_METER_WIDTH as f32).round() as usize;
+        format!(
+            "[{}{}] {:>6.1} dB",
+            "#".repeat(filled),
+            "-".repeat(ASCII_METER_WIDTH - filled),
+            db.max(METER_MIN_DB)
+        )
+    };
+    print!(
+        "\r\x1b[2K{} / {}  L {}  R {}",
+        format_time(elapsed),
+        format_length(length),
+        meter(peaks[0]),
+        meter(peaks[1])
+    );
+    // Ignored like the other writes to stdout, which has no other use while playing.
+    let _ = io::stdout().flush();
+}
+
+// Queues all notes off and all sound off for every channel, cutting off the notes left
+// hanging by a lost note-off while the sequencer carries on.
+fn silence_channels(messages: &mut Vec<Message>) {
+    for channel in 0..16 {
+        for controller in [Sequencer::ALL_NOTES_OFF, Sequencer::ALL_SOUND_OFF] {
+            messages.push(Message::Channel {
+                channel: channel,
+                command: 0xB0,
+                data1: controller,
+                data2: 0,
+            });
+        }
+    }
+}
+
+// Applies an action that changes the playback rather than the window,
+// returning whether the action was one of those.
+fn apply_playback_action(
+    shared: &Shared,
+    action: Action,
+    sound_font_count: usize,
+    sample_rate: u32,
+) -> bool {
+    let mut mix = shared.mix.lock().unwrap();
+    let mut controls = shared.controls.lock().unwrap();
+    match action {
+        Action::Pause => {
+            let mut p = shared.paused.lock().unwrap();
+            *p = !*p;
+        }
+        Action::GainUp => adjust(&mut mix.gain, GAIN_STEP, GAIN_MIN, GAIN_MAX),
+        Action::GainDown => adjust(&mut mix.gain, -GAIN_STEP, GAIN_MIN, GAIN_MAX),
+        Action::SpeedDown => adjust(&mut controls.speed, -SPEED_STEP, SPEED_MIN, SPEED_MAX),
+        Action::SpeedUp => adjust(&mut controls.speed, SPEED_STEP, SPEED_MIN, SPEED_MAX),
+        Action::PanLeft => adjust(&mut mix.pan, -PAN_STEP, -1_f32, 1_f32),
+        Action::PanRight => adjust(&mut mix.pan, PAN_STEP, -1_f32, 1_f32),
+        Action::WidthDown => adjust(&mut mix.width, -WIDTH_STEP, 0_f32, WIDTH_MAX),
+        Action::WidthUp => adjust(&mut mix.width, WIDTH_STEP, 0_f32, WIDTH_MAX),
+        Action::ToggleEffects => {
+            controls.effects = !controls.effects;
+            info!("Reverb and chorus are {}.", on_off(controls.effects));
+        }
+        Action::ToggleMono => {
+            mix.mono = !mix.mono;
+            info!("Mono is {}.", on_off(mix.mono));
+        }
+        Action::EqUp(band) => adjust_eq(&mut mix.eq_gains, band, EQ_STEP_DB),
+        Action::EqDown(band) => adjust_eq(&mut mix.eq_gains, band, -EQ_STEP_DB),
+        Action::ToggleEq => {
+            mix.eq_enabled = !mix.eq_enabled;
+            info!("The EQ is {}.", on_off(mix.eq_enabled));
+        }
+        Action::ToggleDcBlock => {
+            mix.dc_block = !mix.dc_block;
+            info!("The DC blocking is {}.", on_off(mix.dc_block));
+        }
+        Action::ReverbDown => adjust_send(
+            &mut controls.reverb_send,
+            Sequencer::DEFAULT_REVERB_SEND,
+            -EFFECT_SEND_STEP,
+        ),
+        Action::ReverbUp => adjust_send(
+            &mut controls.reverb_send,
+            Sequencer::DEFAULT_REVERB_SEND,
+            EFFECT_SEND_STEP,
+        ),
+        Action::ChorusDown => adjust_send(
+            &mut controls.chorus_send,
+            Sequencer::DEFAULT_CHORUS_SEND,
+            -EFFECT_SEND_STEP,
+        ),
+        Action::ChorusUp => adjust_send(
+            &mut controls.chorus_send,
+            Sequencer::DEFAULT_CHORUS_SEND,
+            EFFECT_SEND_STEP,
+        ),
+        Action::SetLoopStart => {
+            let position = *shared.elapsed.lock().unwrap() as f64 / sample_rate as f64;
+            controls.loop_points.0 = Some(position);
+        }
+        Action::SetLoopEnd => {
+            let position = *shared.elapsed.lock().unwrap() as f64 / sample_rate as f64;
+            controls.loop_points.1 = Some(position);
+        }
+        Action::ClearLoop => controls.loop_points = (None, None),
+        Action::NextSoundFont => {
+            controls.sound_font_index = (controls.sound_font_index + 1) % sound_font_count;
+        }
+        Action::TransposeUp => controls.transpose = (controls.transpose + 1).min(TRANSPOSE_LIMIT),
+        Action::TransposeDown => {
+            controls.transpose = (controls.transpose - 1).max(-TRANSPOSE_LIMIT)
+        }
+        Action::TuneUp => controls.fine_tune = (controls.fine_tune + 1).min(TUNE_LIMIT_CENTS),
+        Action::TuneDown => controls.fine_tune = (controls.fine_tune - 1).max(-TUNE_LIMIT_CENTS),
+        Action::Mute(channel) => controls.muted[channel] = !controls.muted[channel],
+        Action::Solo(channel) => controls.soloed[channel] = !controls.soloed[channel],
+        _ => return false,
+    }
+    true
+}
+
+// Prints the outcome of a key's action, leaving out the successes with --quiet, and returns
+// the message for the status line.
+fn report(result: Result<String, String>) -> String {
+    match result {
+        Ok(message) => {
+            info!("{}", message);
+            message
+        }
+        Err(message) => {
+            eprintln!("{}", message);
+            message
+        }
+    }
+}
+
+// Starts writing the output to a new WAV file named after the time, or finishes
+// the one being written, and returns the message saying which.
+fn toggle_recording(shared: &Shared, sample_rate: u32) -> Result<String, String> {
+    let mut recording = shared.recording.lock().unwrap();
+    match recording.take() {
+        Some(writer) => match writer.finalize() {
+            Ok(()) => Ok("Stopped recording".to_string()),
+            Err(error) => Err(format!("Failed to finish the recording: {}", error)),
+        },
+        None => {
+            let path = format!("recording-{}.wav", timestamp());
+            let writer = File::create(&path)
+                .and_then(|file| WavWriter::new(BufWriter::new(file), sample_rate));
+            match writer {
+                Ok(writer) => {
+                    *recording = Some(writer);
+                    Ok(format!("Recording to '{}'", path))
+                }
+                Err(error) => Err(format!("Failed to create '{}': {}", path, error)),
+            }
+        }
+    }
+}
+
+// Formats the current UTC time as YYYYMMDD-HHMMSS.
+fn timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // Convert the days since 1970-01-01 to a civil date (Howard Hinnant's algorithm).
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+fn format_time(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+// The name of the key with its octave, where 60 is C4.
+fn note_name(key: u8) -> String {
+    const NAMES: [&str; 12] = [
+        "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+    ];
+    format!("{}{}", NAMES[key as usize % 12], key as i32 / 12 - 1)
+}
+
+// A line of the event log: the time, the channel counted from 1 and what the message does.
+fn format_sent_message(message: &SentMessage) -> String {
+    let SentMessage {
+        time,
+        channel,
+        command,
+        data1,
+        data2,
+    } = *message;
+    let description = match command {
+        0x80 => format!("note off  {}", note_name(data1)),
+        0x90 if data2 == 0 => format!("note off  {}", note_name(data1)),
+        0x90 => format!("note on   {} {}", note_name(data1), data2),
+        0xA0 => format!("pressure  {} {}", note_name(data1), data2),
+        0xB0 => format!("control   {} = {}", data1, data2),
+        0xC0 => format!("program   {} {}", data1 + 1, gm::program_name(data1)),
+        0xD0 => format!("pressure  {}", data1),
+        0xE0 => format!(
+            "bend      {:+}",
+            ((data2 as i32) << 7 | data1 as i32) - 8192
+        ),
+        _ => format!("{:02X} {} {}", command, data1, data2),
+    };
+    format!("{:>9.3}  ch {:<2}  {}", time, channel + 1, description)
+}
+
+// The position as bar:beat:tick.
+fn format_bar_position(position: BarPosition) -> String {
+    format!("{}:{}:{:03}", position.bar, position.beat, position.tick)
+}
+
+// A file without any notes, or the MIDI input, has no length to show.
+fn format_length(seconds: f64) -> String {
+    if seconds > 0_f64 {
+        format_time(seconds)
+    } else {
+        "unknown length".to_string()
+    }
+}
+
+fn adjust(value: &mut f32, delta: f32, min: f32, max: f32) {
+    // Snap to the step size so that repeated adjustments don't accumulate rounding errors.
+    let step = delta.abs();
+    let steps = ((*value + delta) / step).round();
+    *value = (step * steps).clamp(min, max);
+}
+
+// Nudges a send level, starting from the synthesizer's default
+// if the file has been deciding it so far.
+fn adjust_send(value: &mut Option<u8>, default: u8, delta: i32) {
+    let current = value.unwrap_or(default) as i32;
+    *value = Some((current + delta).clamp(0, 127) as u8);
+}
+
+fn adjust_eq(gains: &mut [f32; 3], band: usize, delta: f32) {
+    gains[band] = (gains[band] + delta).clamp(-EQ_LIMIT_DB, EQ_LIMIT_DB);
+}
+
+// The gains of the bands, or "off" when the EQ is bypassed.
+fn eq_settings(gains: [f32; 3], enabled: bool) -> String {
+    if enabled {
+        format!("{:+} {:+} {:+} dB", gains[0], gains[1], gains[2])
+    } else {
+        "off".to_string()
+    }
+}
+
+fn send_level(value: Option<u8>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "file".to_string(),
+    }
+}
+
+// Moves the color towards white by the given fraction.
+fn brighten(col: Color, amount: f32) -> Color {
+    let lerp = |a: u8| (a as f32 + (255_f32 - a as f32) * amount) as u8;
+    Color::rgb(lerp(col.r), lerp(col.g), lerp(col.b))
+}
+
+// Scales the color towards black, to the given fraction of its brightness.
+fn dim(col: Color, amount: f32) -> Color {
+    let scale = |a: u8| (a as f32 * amount) as u8;
+    Color::rgb(scale(col.r), scale(col.g), scale(col.b))
+}
+
+// Interpolates from blue for quiet passages to orange for dense ones.
+fn activity_color(activity: f32) -> Color {
+    let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * activity) as u8;
+    Color::rgb(lerp(0, 255), lerp(100, 140), lerp(200, 0))
+}
+
+// Draws a trace around the middle of the area, with the margin left on both sides
+// and the samples scaled by the visual gain.
+fn draw_waveform(
+    window: &mut RenderWindow,
+    area: FloatRect,
+    data: &[f32],
+    style: WaveformStyle,
+    col: Color,
+    margin: f32,
+    gain: f32,
+) {
+    // Stretch the trace to the area, keeping the proportions of the 1024x768 layout.
+    let margin = clamp_margin(margin, area.width);
+    let left = area.left + margin;
+    let x_scale = (area.width - 2_f32 * margin) / WAVEFORM_LENGTH as f32;
+    let baseline = area.top + 0.5_f32 * area.height;
+    let amplitude = gain * WAVEFORM_SCALE * area.height;
+
+    if style == WaveformStyle::Line {
+        let mut vs: [Vertex; WAVEFORM_LENGTH] = [Vertex::default(); WAVEFORM_LENGTH];
+        for i in 0..WAVEFORM_LENGTH {
+            let x = left + x_scale * (i as f32 + 0.5_f32);
+            vs[i].color = col;
+            vs[i].position = Vector2::new(x, -amplitude * data[i] + baseline);
+        }
+        window.draw_primitives(&vs[..], PrimitiveType::LINE_STRIP, &RenderStates::DEFAULT);
+        return;
+    }
+
     let mut vs: [Vertex; 4 * WAVEFORM_LENGTH] = [Vertex::default(); 4 * WAVEFORM_LENGTH];
 
     for i in 0..WAVEFORM_LENGTH {
         let offset = 4 * i;
         let val = data[i].abs();
+        let x0 = left + x_scale * (i + 0) as f32;
+        let x1 = left + x_scale * (i + 1) as f32;
+        vs[offset + 0].color = col;
+        vs[offset + 0].position = Vector2::new(x0, -amplitude * val + baseline);
+        vs[offset + 1].color = col;
+        vs[offset + 1].position = Vector2::new(x1, -amplitude * val + baseline);
+        vs[offset + 2].color = col;
+        vs[offset + 2].position = Vector2::new(x1, amplitude * val + baseline);
+        vs[offset + 3].color = col;
+        vs[offset + 3].position = Vector2::new(x0, amplitude * val + baseline);
+    }
+
+    window.draw_primitives(&vs[..], PrimitiveType::QUADS, &RenderStates::DEFAULT);
+}
+
+// Darkens the margins beside the waveform, down to the progress bar.
+fn draw_margins(window: &mut RenderWindow, area: FloatRect, margin: f32) {
+    let margin = clamp_margin(margin, area.width);
+    if margin <= 0_f32 {
+        return;
+    }
+    let height = area.height - PROGRESS_BAR_HEIGHT;
+
+    let mut panel = RectangleShape::new();
+    panel.set_size((margin, height));
+    panel.set_fill_color(Color::rgba(0, 0, 0, 96));
+    panel.set_position((area.left, area.top));
+    window.draw(&panel);
+    panel.set_position((area.left + area.width - margin, area.top));
+    window.draw(&panel);
+}
+
+// Keeps at least half of the width for the waveform.
+fn clamp_margin(margin: f32, width: f32) -> f32 {
+    margin.clamp(0_f32, 0.25_f32 * width)
+}
+
+// Plots left against right, rotated so that mono content is a vertical line
+// and out-of-phase content a horizontal one, within a circle at the center.
+fn draw_scope(window: &mut RenderWindow, area: FloatRect, left: &[f32], right: &[f32]) {
+    let center = Vector2::new(
+        area.left + 0.5_f32 * area.width,
+        area.top + 0.5_f32 * area.height,
+    );
+    let radius = 0.45_f32 * area.width.min(area.height);
+
+    let outline = Color::rgb(0, 60, 110);
+    let cs: Vec<Vertex> = (0..=SCOPE_CIRCLE_SEGMENTS)
+        .map(|i| {
+            let angle = 2_f32 * std::f32::consts::PI * i as f32 / SCOPE_CIRCLE_SEGMENTS as f32;
+            let position = center + Vector2::new(angle.cos(), angle.sin()) * radius;
+            Vertex::with_pos_color(position, outline)
+        })
+        .collect();
+    window.draw_primitives(&cs[..], PrimitiveType::LINE_STRIP, &RenderStates::DEFAULT);
+
+    let col = Color::rgb(0, 200, 255);
+    let vs: Vec<Vertex> = left
+        .iter()
+        .zip(right)
+        .map(|(&l, &r)| {
+            let x = 0.5_f32 * (r - l).clamp(-2_f32, 2_f32);
+            let y = -0.5_f32 * (l + r).clamp(-2_f32, 2_f32);
+            Vertex::with_pos_color(center + Vector2::new(x, y) * radius, col)
+        })
+        .collect();
+    window.draw_primitives(&vs[..], PrimitiveType::LINE_STRIP, &RenderStates::DEFAULT);
+}
+
+// Draws the note density as a strip just above the progress bar,
+// fading from transparent for no notes to the accent color for the busiest part.
+fn draw_density(window: &mut RenderWindow, density: &[f32], accent: Color) {
+    let size = window.size();
+    let bottom = size.y as f32 - PROGRESS_BAR_HEIGHT;
+    let top = bottom - DENSITY_STRIP_HEIGHT;
+    let width = size.x as f32 / density.len() as f32;
+
+    let mut vs = Vec::with_capacity(4 * density.len());
+    for (i, &value) in density.iter().enumerate() {
+        let col = Color::rgba(accent.r, accent.g, accent.b, (255_f32 * value) as u8);
+        let left = width * i as f32;
+        let right = left + width;
+        vs.push(Vertex::with_pos_color(Vector2::new(left, top), col));
+        vs.push(Vertex::with_pos_color(Vector2::new(right, top), col));
+        vs.push(Vertex::with_pos_color(Vector2::new(right, bottom), col));
+        vs.push(Vertex::with_pos_color(Vector2::new(left, bottom), col));
+    }
+    window.draw_primitives(&vs[..], PrimitiveType::QUADS, &RenderStates::DEFAULT);
+}
+
+// Draws the bar with the markers at the given fractions of its length.
+fn draw_progress_bar(window: &mut RenderWindow, progress: f32, markers: &[f32], accent: Color) {
+    let size = window.size();
+    let width = size.x as f32;
+    let bottom = size.y as f32;
+    let top = bottom - PROGRESS_BAR_HEIGHT;
+    let split = width * progress.clamp(0_f32, 1_f32);
+
+    let filled = accent;
+    let unfilled = Color::rgb(accent.r / 3, accent.g / 3, accent.b / 3);
+    let vs = [
+        Vertex::with_pos_color(Vector2::new(0_f32, top), filled),
+        Vertex::with_pos_color(Vector2::new(split, top), filled),
+        Vertex::with_pos_color(Vector2::new(split, bottom), filled),
+        Vertex::with_pos_color(Vector2::new(0_f32, bottom), filled),
+        Vertex::with_pos_color(Vector2::new(split, top), unfilled),
+        Vertex::with_pos_color(Vector2::new(width, top), unfilled),
+        Vertex::with_pos_color(Vector2::new(width, bottom), unfilled),
+        Vertex::with_pos_color(Vector2::new(split, bottom), unfilled),
+    ];
+
+    window.draw_primitives(&vs[..], PrimitiveType::QUADS, &RenderStates::DEFAULT);
+
+    let marker = Color::rgb(255, 200, 0);
+    let mut ms = Vec::with_capacity(4 * markers.len());
+    for &position in markers {
+        let x = width * position.clamp(0_f32, 1_f32);
+        ms.push(Vertex::with_pos_color(Vector2::new(x - 1_f32, top), marker));
+        ms.push(Vertex::with_pos_color(Vector2::new(x + 1_f32, top), marker));
+        ms.push(Vertex::with_pos_color(
+            Vector2::new(x + 1_f32, bottom),
+            marker,
+        ));
+        ms.push(Vertex::with_pos_color(
+            Vector2::new(x - 1_f32, bottom),
+            marker,
+        ));
+    }
+    window.draw_primitives(&ms[..], PrimitiveType::QUADS, &RenderStates::DEFAULT);
+}
+
+// Draws the text in the top-left corner, under the channel indicators,
+// on a translucent panel so that it stays readable over the visualization.
+fn draw_hud(window: &mut RenderWindow, font: &Font, string: &str) {
+    let mut text = Text::new(string, font, HUD_FONT_SIZE);
+    text.set_fill_color(Color::WHITE);
+    text.set_position((16_f32, 36_f32));
+
+    let bounds = text.global_bounds();
+    let mut panel = RectangleShape::new();
+    panel.set_position((bounds.left - 8_f32, bounds.top - 8_f32));
+    panel.set_size((bounds.width + 16_f32, bounds.height + 16_f32));
+    panel.set_fill_color(Color::rgba(0, 0, 0, 160));
+
+    window.draw(&panel);
+    window.draw(&text);
+}
+
+// Dims the whole window and draws the text in the middle of it.
+fn draw_help(window: &mut RenderWindow, font: &Font, string: &str) {
+    let size = window.size();
+    let mut shade = RectangleShape::new();
+    shade.set_size((size.x as f32, size.y as f32));
+    shade.set_fill_color(Color::rgba(0, 0, 0, 192));
+    window.draw(&shade);
+
+    let mut text = Text::new(string, font, HUD_FONT_SIZE);
+    text.set_fill_color(Color::WHITE);
+    let bounds = text.local_bounds();
+    let x = (0.5_f32 * (size.x as f32 - bounds.width)).max(8_f32);
+    let y = (0.5_f32 * (size.y as f32 - bounds.height)).max(8_f32);
+    text.set_position(((x - bounds.left).round(), (y - bounds.top).round()));
+    window.draw(&text);
+}
+
+// Draws the text on a panel just above the progress bar at the given fraction of its width,
+// kept inside the window.
+fn draw_tooltip(window: &mut RenderWindow, font: &Font, string: &str, fraction: f32) {
+    let size = window.size();
+    let mut text = Text::new(string, font, HUD_FONT_SIZE);
+    text.set_fill_color(Color::WHITE);
+    let bounds = text.local_bounds();
+    let x = (fraction * size.x as f32 - 0.5_f32 * bounds.width)
+        .clamp(8_f32, (size.x as f32 - bounds.width - 8_f32).max(8_f32));
+    let y = size.y as f32 - PROGRESS_BAR_HEIGHT - DENSITY_STRIP_HEIGHT - 8_f32 - bounds.height;
+    text.set_position((x - bounds.left, y - bounds.top));
+
+    let mut panel = RectangleShape::new();
+    panel.set_position((x - 4_f32, y - 4_f32));
+    panel.set_size((bounds.width + 8_f32, bounds.height + 8_f32));
+    panel.set_fill_color(Color::rgba(0, 0, 0, 160));
+
+    window.draw(&panel);
+    window.draw(&text);
+}
+
+// Draws the messages on a panel in the bottom-right corner, the latest at the bottom.
+fn draw_event_log(window: &mut RenderWindow, font: &Font, log: &VecDeque<String>) {
+    let size = window.size();
+    let string = match log.is_empty() {
+        true => "No messages yet".to_string(),
+        false => log.iter().cloned().collect::<Vec<_>>().join("\n"),
+    };
+    let mut text = Text::new(&string, font, HUD_FONT_SIZE);
+    text.set_fill_color(Color::WHITE);
+    let bounds = text.local_bounds();
+    let x = (size.x as f32 - bounds.width - 16_f32).max(8_f32);
+    let y = size.y as f32 - PROGRESS_BAR_HEIGHT - DENSITY_STRIP_HEIGHT - 16_f32 - bounds.height;
+    text.set_position(((x - bounds.left).round(), (y - bounds.top).round()));
+
+    let mut panel = RectangleShape::new();
+    panel.set_position((x - 8_f32, y - 8_f32));
+    panel.set_size((bounds.width + 16_f32, bounds.height + 16_f32));
+    panel.set_fill_color(Color::rgba(0, 0, 0, 160));
+
+    window.draw(&panel);
+    window.draw(&text);
+}
+
+// Draws a red "CLIP" box to the left of the meters.
+fn draw_clip_indicator(window: &mut RenderWindow, font: Option<&Font>) {
+    let size = window.size();
+    let right = size.x as f32 - 8_f32 - 2_f32 * (METER_WIDTH + 4_f32) - 4_f32;
+    let width = 48_f32;
+    let height = 24_f32;
+
+    let mut panel = RectangleShape::new();
+    panel.set_position((right - width, 16_f32));
+    panel.set_size((width, height));
+    panel.set_fill_color(Color::rgb(255, 40, 40));
+    window.draw(&panel);
+
+    if let Some(font) = font {
+        let mut text = Text::new("CLIP", font, HUD_FONT_SIZE);
+        text.set_fill_color(Color::WHITE);
+        let bounds = text.local_bounds();
+        text.set_position((
+            right - 0.5_f32 * (width + bounds.width) - bounds.left,
+            16_f32 + 0.5_f32 * (height - bounds.height) - bounds.top,
+        ));
+        window.draw(&text);
+    }
+}
+
+// Draws "PANIC" on a red panel in the middle of the top edge.
+fn draw_panic_flash(window: &mut RenderWindow, font: Option<&Font>) {
+    let size = window.size();
+    let width = 96_f32;
+    let height = 32_f32;
+    let left = 0.5_f32 * (size.x as f32 - width);
+
+    let mut panel = RectangleShape::new();
+    panel.set_position((left, 16_f32));
+    panel.set_size((width, height));
+    panel.set_fill_color(Color::rgb(255, 40, 40));
+    window.draw(&panel);
+
+    if let Some(font) = font {
+        let mut text = Text::new("PANIC", font, HUD_FONT_SIZE);
+        text.set_fill_color(Color::WHITE);
+        let bounds = text.local_bounds();
+        text.set_position((
+            left + 0.5_f32 * (width - bounds.width) - bounds.left,
+            16_f32 + 0.5_f32 * (height - bounds.height) - bounds.top,
+        ));
+        window.draw(&text);
+    }
+}
+
+// Draws a red dot with "REC" to the left of the clip indicator.
+fn draw_recording_indicator(window: &mut RenderWindow, font: Option<&Font>) {
+    let size = window.size();
+    let right = size.x as f32 - 8_f32 - 2_f32 * (METER_WIDTH + 4_f32) - 4_f32 - 48_f32 - 8_f32;
+    let radius = 8_f32;
+
+    let mut dot = CircleShape::new(radius, 24);
+    dot.set_position((right - 2_f32 * radius, 20_f32));
+    dot.set_fill_color(Color::rgb(255, 40, 40));
+    window.draw(&dot);
+
+    if let Some(font) = font {
+        let mut text = Text::new("REC", font, HUD_FONT_SIZE);
+        text.set_fill_color(Color::WHITE);
+        let bounds = text.local_bounds();
+        text.set_position((
+            right - 2_f32 * radius - 6_f32 - bounds.width - bounds.left,
+            20_f32 + radius - 0.5_f32 * bounds.height - bounds.top,
+        ));
+        window.draw(&text);
+    }
+}
+
+// Draws the render loads as a line over a panel in the bottom-left corner, with full scale
+// at the maximum load and a red line at 1, beyond which the batches render too slowly.
+fn draw_render_loads(window: &mut RenderWindow, loads: &VecDeque<f32>) {
+    let size = window.size();
+    let left = 8_f32;
+    let width = RENDER_LOAD_HISTORY as f32;
+    let bottom = size.y as f32 - PROGRESS_BAR_HEIGHT - DENSITY_STRIP_HEIGHT - 8_f32;
+    let top = bottom - RENDER_LOAD_GRAPH_HEIGHT;
+    let y = |load: f32| bottom - (load / RENDER_LOAD_MAX).min(1_f32) * RENDER_LOAD_GRAPH_HEIGHT;
+
+    let mut panel = RectangleShape::new();
+    panel.set_position((left, top));
+    panel.set_size((width, RENDER_LOAD_GRAPH_HEIGHT));
+    panel.set_fill_color(Color::rgba(0, 0, 0, 160));
+    window.draw(&panel);
+
+    let mut budget = RectangleShape::new();
+    budget.set_position((left, y(1_f32)));
+    budget.set_size((width, 1_f32));
+    budget.set_fill_color(Color::rgb(255, 40, 40));
+    window.draw(&budget);
+
+    // The newest load is at the right edge.
+    let offset = RENDER_LOAD_HISTORY - loads.len();
+    let vs: Vec<Vertex> = loads
+        .iter()
+        .enumerate()
+        .map(|(t, &load)| {
+            Vertex::with_pos_color(
+                Vector2::new(left + (offset + t) as f32, y(load)),
+                Color::rgb(0, 200, 100),
+            )
+        })
+        .collect();
+    window.draw_primitives(&vs[..], PrimitiveType::LINE_STRIP, &RenderStates::DEFAULT);
+}
+
+// Draws a gauge from -1 to 1 left of the clip indicator, with a needle at the correlation,
+// green while it's safe in mono and red once it's below the warning level.
+fn draw_correlation(window: &mut RenderWindow, value: f32) {
+    let size = window.size();
+    let right = size.x as f32 - 8_f32 - 2_f32 * (METER_WIDTH + 4_f32) - 4_f32 - 48_f32 - 8_f32;
+    let left = right - CORRELATION_GAUGE_WIDTH;
+    let top = 22_f32;
+    let height = 12_f32;
+
+    let mut track = RectangleShape::new();
+    track.set_position((left, top));
+    track.set_size((CORRELATION_GAUGE_WIDTH, height));
+    track.set_fill_color(Color::rgba(0, 0, 0, 160));
+    window.draw(&track);
+
+    // The middle marks the channels having nothing in common.
+    let mut center = RectangleShape::new();
+    center.set_position((left + 0.5_f32 * CORRELATION_GAUGE_WIDTH - 0.5_f32, top));
+    center.set_size((1_f32, height));
+    center.set_fill_color(Color::rgb(96, 96, 96));
+    window.draw(&center);
+
+    let x = left + 0.5_f32 * (value.clamp(-1_f32, 1_f32) + 1_f32) * CORRELATION_GAUGE_WIDTH;
+    let mut needle = RectangleShape::new();
+    needle.set_position((x - 1.5_f32, top - 2_f32));
+    needle.set_size((3_f32, height + 4_f32));
+    needle.set_fill_color(if value < CORRELATION_WARNING {
+        Color::rgb(255, 40, 40)
+    } else {
+        Color::rgb(0, 200, 100)
+    });
+    window.draw(&needle);
+}
+
+// Draws a row of indicators for the channels that can be muted or soloed:
+// yellow when soloed, gray when muted, dim when silenced by another solo.
+fn draw_channel_states(window: &mut RenderWindow, muted: &[bool; 16], soloed: &[bool; 16]) {
+    let any_soloed = soloed.iter().any(|&on| on);
+    let size = 12_f32;
+    let top = 8_f32;
+
+    let mut vs: [Vertex; 4 * 10] = [Vertex::default(); 4 * 10];
+
+    for ch in 0..10 {
+        let col = if soloed[ch] {
+            Color::rgb(255, 200, 0)
+        } else if muted[ch] {
+            Color::rgb(60, 60, 60)
+        } else if any_soloed {
+            Color::rgb(0, 70, 35)
+        } else {
+            Color::rgb(0, 200, 100)
+        };
+
+        let offset = 4 * ch;
+        let left = 8_f32 + ch as f32 * (size + 4_f32);
+        let right = left + size;
+        let bottom = top + size;
+        vs[offset + 0].color = col;
+        vs[offset + 0].position = Vector2::new(left, top);
+        vs[offset + 1].color = col;
+        vs[offset + 1].position = Vector2::new(right, top);
+        vs[offset + 2].color = col;
+        vs[offset + 2].position = Vector2::new(right, bottom);
+        vs[offset + 3].color = col;
+        vs[offset + 3].position = Vector2::new(left, bottom);
+    }
+
+    window.draw_primitives(&vs[..], PrimitiveType::QUADS, &RenderStates::DEFAULT);
+}
+
+fn draw_meters(window: &mut RenderWindow, levels: &[f32; 2]) {
+    let size = window.size();
+    let top = 16_f32;
+    let bottom = size.y as f32 - PROGRESS_BAR_HEIGHT - 16_f32;
+
+    let mut vs: [Vertex; 8] = [Vertex::default(); 8];
+
+    for ch in 0..2 {
+        let val = ((levels[ch] - METER_MIN_DB) / -METER_MIN_DB).clamp(0_f32, 1_f32);
+        let col = if levels[ch] >= METER_WARNING_DB {
+            Color::rgb(255, 40, 40)
+        } else {
+            Color::rgb(0, 200, 100)
+        };
+
+        let offset = 4 * ch;
+        let right = size.x as f32 - 8_f32 - (1 - ch) as f32 * (METER_WIDTH + 4_f32);
+        let left = right - METER_WIDTH;
+        let y = bottom - (bottom - top) * val;
+        vs[offset + 0].color = col;
+        vs[offset + 0].position = Vector2::new(left, y);
+        vs[offset + 1].color = col;
+        vs[offset + 1].position = Vector2::new(right, y);
+        vs[offset + 2].color = col;
+        vs[offset + 2].position = Vector2::new(right, bottom);
+        vs[offset + 3].color = col;
+        vs[offset + 3].position = Vector2::new(left, bottom);
+    }
+
+    window.draw_primitives(&vs[..], PrimitiveType::QUADS, &RenderStates::DEFAULT);
+}
+
+// Draws a 4 by 4 grid of bars for the channels to the left of the master meters,
+// with the drum channels in orange.
+fn draw_channel_meters(window: &mut RenderWindow, levels: &[f32; 16], drums: &[bool; 16]) {
+    let size = window.size();
+    let right = size.x as f32 - 8_f32 - 2_f32 * (METER_WIDTH + 4_f32) - 8_f32;
+    let bottom = size.y as f32 - PROGRESS_BAR_HEIGHT - 16_f32;
+    let step = CHANNEL_METER_SIZE + 4_f32;
+
+    let mut vs: [Vertex; 8 * 16] = [Vertex::default(); 8 * 16];
+
+    for ch in 0..16 {
+        let left = right - (4 - ch % 4) as f32 * step + 4_f32;
+        let top = bottom - (4 - ch / 4) as f32 * step + 4_f32;
+        let cell_right = left + CHANNEL_METER_SIZE;
+        let cell_bottom = top + CHANNEL_METER_SIZE;
+        let y = cell_bottom - CHANNEL_METER_SIZE * levels[ch].clamp(0_f32, 1_f32);
+        let col = if drums[ch] {
+            Color::rgb(255, 140, 0)
+        } else {
+            Color::rgb(0, 200, 100)
+        };
+
+        // The dark cell first, then the bar over it.
+        let offset = 8 * ch;
+        let quads = [(top, Color::rgba(0, 0, 0, 160)), (y, col)];
+        for (i, (bar_top, col)) in quads.into_iter().enumerate() {
+            let offset = offset + 4 * i;
+            vs[offset + 0].color = col;
+            vs[offset + 0].position = Vector2::new(left, bar_top);
+            vs[offset + 1].color = col;
+            vs[offset + 1].position = Vector2::new(cell_right, bar_top);
+            vs[offset + 2].color = col;
+            vs[offset + 2].position = Vector2::new(cell_right, cell_bottom);
+            vs[offset + 3].color = col;
+            vs[offset + 3].position = Vector2::new(left, cell_bottom);
+        }
+    }
+
+    window.draw_primitives(&vs[..], PrimitiveType::QUADS, &RenderStates::DEFAULT);
+}
+
+// Groups the FFT bins into bands spaced logarithmically from the minimum frequency
+// up to Nyquist, with the levels scaled so that 0 is the floor and 1 is full scale.
+fn spectrum_bands(samples: &[f32], window: &[f32], sample_rate: u32) -> [f32; SPECTRUM_BAR_COUNT] {
+    let spectrum = fft::spectrum_db(samples, window);
+    let bin_width = sample_rate as f32 / samples.len() as f32;
+
+    let log_min = SPECTRUM_MIN_FREQUENCY.ln();
+    let log_max = (sample_rate as f32 / 2_f32).ln();
+
+    let mut bands = [0_f32; SPECTRUM_BAR_COUNT];
+
+    for i in 0..SPECTRUM_BAR_COUNT {
+        let f0 = (log_min + (log_max - log_min) * i as f32 / SPECTRUM_BAR_COUNT as f32).exp();
+        let f1 = (log_min + (log_max - log_min) * (i + 1) as f32 / SPECTRUM_BAR_COUNT as f32).exp();
+        let b0 = ((f0 / bin_width) as usize).clamp(1, spectrum.len() - 1);
+        let b1 = ((f1 / bin_width) as usize).clamp(b0 + 1, spectrum.len());
+        let db = spectrum[b0..b1]
+            .iter()
+            .fold(SPECTRUM_MIN_DB, |a, &b| a.max(b));
+        bands[i] = (db - SPECTRUM_MIN_DB) / -SPECTRUM_MIN_DB;
+    }
+
+    bands
+}
+
+fn draw_spectrum(window: &mut RenderWindow, area: FloatRect, bands: &[f32; SPECTRUM_BAR_COUNT]) {
+    let bar_width = area.width / SPECTRUM_BAR_COUNT as f32;
+    let bottom = area.top + area.height;
+    let max_height = 700_f32 / 768_f32 * area.height;
+
+    let mut vs: [Vertex; 4 * SPECTRUM_BAR_COUNT] = [Vertex::default(); 4 * SPECTRUM_BAR_COUNT];
+
+    for i in 0..SPECTRUM_BAR_COUNT {
+        let val = bands[i];
+
+        let offset = 4 * i;
+        let left = area.left + i as f32 * bar_width + 1_f32;
+        let right = area.left + (i + 1) as f32 * bar_width - 1_f32;
+        let top = bottom - max_height * val;
         let col = Color::rgb(0, 100, 200);
         vs[offset + 0].color = col;
-        vs[offset + 0].position = Vector2::new((i + 0) as f32, -300_f32 * val + 384_f32);
+        vs[offset + 0].position = Vector2::new(left, top);
         vs[offset + 1].color = col;
-        vs[offset + 1].position = Vector2::new((i + 1) as f32, -300_f32 * val + 384_f32);
+        vs[offset + 1].position = Vector2::new(right, top);
         vs[offset + 2].color = col;
-        vs[offset + 2].position = Vector2::new((i + 1) as f32, 300_f32 * val + 384_f32);
+        vs[offset + 2].position = Vector2::new(right, bottom);
         vs[offset + 3].color = col;
-        vs[offset + 3].position = Vector2::new((i + 0) as f32, 300_f32 * val + 384_f32);
+        vs[offset + 3].position = Vector2::new(left, bottom);
+    }
+
+    window.draw_primitives(&vs[..], PrimitiveType::QUADS, &RenderStates::DEFAULT);
+}
+
+// Draws the band history with the newest column at the right edge
+// and the lowest band at the bottom. The older columns that don't fit are cut off.
+fn draw_spectrogram(
+    window: &mut RenderWindow,
+    area: FloatRect,
+    history: &VecDeque<[f32; SPECTRUM_BAR_COUNT]>,
+) {
+    let history: Vec<_> = history
+        .iter()
+        .skip(history.len().saturating_sub(area.width as usize))
+        .collect();
+    let row_height = area.height / SPECTRUM_BAR_COUNT as f32;
+    let first_x = area.left + area.width - history.len() as f32;
+
+    let mut vs = vec![Vertex::default(); 4 * SPECTRUM_BAR_COUNT * history.len()];
+
+    for (t, bands) in history.iter().enumerate() {
+        let left = first_x + t as f32;
+        let right = left + 1_f32;
+        for i in 0..SPECTRUM_BAR_COUNT {
+            let offset = 4 * (SPECTRUM_BAR_COUNT * t + i);
+            let bottom = area.top + area.height - i as f32 * row_height;
+            let top = bottom - row_height;
+            let col = spectrogram_color(bands[i]);
+            vs[offset + 0].color = col;
+            vs[offset + 0].position = Vector2::new(left, top);
+            vs[offset + 1].color = col;
+            vs[offset + 1].position = Vector2::new(right, top);
+            vs[offset + 2].color = col;
+            vs[offset + 2].position = Vector2::new(right, bottom);
+            vs[offset + 3].color = col;
+            vs[offset + 3].position = Vector2::new(left, bottom);
+        }
     }
 
     window.draw_primitives(&vs[..], PrimitiveType::QUADS, &RenderStates::DEFAULT);
 }
+
+// Draws an 88-key piano at the bottom of the area above the progress bar,
+// with the held keys lit in the color of the channel playing them.
+fn draw_keyboard(window: &mut RenderWindow, area: FloatRect, keys: &[Option<u8>; 128]) {
+    let is_black = |key: u8| matches!(key % 12, 1 | 3 | 6 | 8 | 10);
+    let white_count = (KEYBOARD_LOWEST_KEY..=KEYBOARD_HIGHEST_KEY)
+        .filter(|&key| !is_black(key))
+        .count();
+
+    let bottom = area.top + area.height - PROGRESS_BAR_HEIGHT;
+    let top = bottom - KEYBOARD_HEIGHT * area.height;
+    let white_width = area.width / white_count as f32;
+    let black_width = 0.6_f32 * white_width;
+    let black_bottom = top + 0.6_f32 * (bottom - top);
+
+    // The white keys are drawn first so that the black ones overlap them.
+    let mut whites = Vec::with_capacity(4 * white_count);
+    let mut blacks = Vec::new();
+    let mut white_index = 0;
+    for key in KEYBOARD_LOWEST_KEY..=KEYBOARD_HIGHEST_KEY {
+        let held = keys[key as usize].map(channel_color);
+        let (vs, left, right, key_bottom, col) = if is_black(key) {
+            let center = area.left + white_index as f32 * white_width;
+            let col = held.unwrap_or(Color::rgb(20, 20, 20));
+            let left = center - 0.5_f32 * black_width;
+            (&mut blacks, left, left + black_width, black_bottom, col)
+        } else {
+            let left = area.left + white_index as f32 * white_width;
+            white_index += 1;
+            let col = held.unwrap_or(Color::rgb(230, 230, 230));
+            // The gap between the white keys shows the background.
+            let (left, right) = (left + 1_f32, left + white_width - 1_f32);
+            (&mut whites, left, right, bottom, col)
+        };
+        vs.push(Vertex::with_pos_color(Vector2::new(left, top), col));
+        vs.push(Vertex::with_pos_color(Vector2::new(right, top), col));
+        vs.push(Vertex::with_pos_color(Vector2::new(right, key_bottom), col));
+        vs.push(Vertex::with_pos_color(Vector2::new(left, key_bottom), col));
+    }
+
+    window.draw_primitives(&whites[..], PrimitiveType::QUADS, &RenderStates::DEFAULT);
+    window.draw_primitives(&blacks[..], PrimitiveType::QUADS, &RenderStates::DEFAULT);
+}
+
+// Spreads the channels around the hue circle.
+fn channel_color(channel: u8) -> Color {
+    let hue = 6_f32 * channel as f32 / 16_f32;
+    let ramp = |offset: f32| {
+        let distance = ((hue - offset).rem_euclid(6_f32) - 3_f32).abs();
+        (255_f32 * (distance - 1_f32).clamp(0_f32, 1_f32)) as u8
+    };
+    Color::rgb(ramp(0_f32), ramp(2_f32), ramp(4_f32))
+}
+
+// Maps a level from 0 to 1 onto a ramp from the background through blue and red to yellow.
+fn spectrogram_color(val: f32) -> Color {
+    let ramp = [
+        (0_f32, 32_f32, 64_f32),
+        (0_f32, 100_f32, 200_f32),
+        (220_f32, 40_f32, 80_f32),
+        (255_f32, 230_f32, 80_f32),
+    ];
+    let x = val.clamp(0_f32, 1_f32) * (ramp.len() - 1) as f32;
+    let i = (x as usize).min(ramp.len() - 2);
+    let f = x - i as f32;
+    let lerp = |a: f32, b: f32| (a + (b - a) * f) as u8;
+    Color::rgb(
+        lerp(ramp[i].0, ramp[i + 1].0),
+        lerp(ramp[i].1, ramp[i + 1].1),
+        lerp(ramp[i].2, ramp[i + 1].2),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Range;
+
+    // A SoundFont with a single sine wave preset, small enough to keep with the sources.
+    const SOUND_FONT: &[u8] = include_bytes!("../resources/tests/sine.sf2");
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    // A format 1 file at 480 ticks per beat with the given track chunks,
+    // each of which is the events without the end of track.
+    fn midi_file(tracks: &[&[u8]]) -> Vec<u8> {
+        let mut data = b"MThd".to_vec();
+        data.extend_from_slice(&6_u32.to_be_bytes());
+        data.extend_from_slice(&1_u16.to_be_bytes());
+        data.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+        data.extend_from_slice(&480_u16.to_be_bytes());
+        for events in tracks {
+            let end_of_track = [0x00, 0xFF, 0x2F, 0x00];
+            data.extend_from_slice(b"MTrk");
+            data.extend_from_slice(&((events.len() + end_of_track.len()) as u32).to_be_bytes());
+            data.extend_from_slice(events);
+            data.extend_from_slice(&end_of_track);
+        }
+        data
+    }
+
+    // A stream playing the file, set up the way --render does.
+    fn stream(data: &[u8], play_loop: bool) -> MidiMusicStream {
+        let sound_font = Arc::new(SoundFont::new(&mut &SOUND_FONT[..]).unwrap());
+        let (midi_file, midi_events) = parse_midi_file(data).unwrap();
+        let midi_events = Arc::new(midi_events);
+        let mut sequencer =
+            MidiMusicStream::create_sequencer(&sound_font, SAMPLE_RATE, 64, true).unwrap();
+        sequencer.play(&midi_events, play_loop);
+        let shared = Shared::new(1_f32, false, false, false, [false; 16]);
+        let mut stream = MidiMusicStream::new(
+            sequencer,
+            vec![sound_font],
+            Arc::new(midi_file),
+            midi_events,
+            play_loop,
+            SAMPLE_RATE,
+            shared,
+        );
+        stream.set_visualized(false);
+        stream.set_offline(true);
+        stream
+    }
+
+    // Renders the file to a WAV file in memory the way --render does, with the dither on,
+    // as it's the one part of the output made from random numbers.
+    fn render(data: &[u8], configure: impl FnOnce(&mut MidiMusicStream)) -> Vec<u8> {
+        let mut stream = stream(data, false);
+        stream.set_dither(true);
+        configure(&mut stream);
+
+        let mut output = Cursor::new(Vec::new());
+        write_wav(&mut stream, &mut output, &AtomicBool::new(false)).unwrap();
+        output.into_inner()
+    }
+
+    #[test]
+    fn renders_are_identical() {
+        // Two notes a beat apart, the second over a program change.
+        let data = midi_file(&[&[
+            0x00, 0x90, 0x3C, 0x64, 0x83, 0x60, 0x80, 0x3C, 0x00, 0x00, 0xC0, 0x05, 0x00, 0x90,
+            0x40, 0x64, 0x83, 0x60, 0x80, 0x40, 0x00,
+        ]]);
+        let first = render(&data, |_| ());
+        let second = render(&data, |_| ());
+        // More than the header, with some sound in it.
+        assert!(first.len() > 44);
+        assert!(first[44..].iter().any(|&byte| byte != 0));
+        assert!(first == second);
+    }
+
+    #[test]
+    fn duration_counts_every_pass() {
+        // Half a second at the default tempo, played three times but cut off
+        // partway through the third.
+        let data = midi_file(&[&[0x00, 0x90, 0x3C, 0x64, 0x83, 0x60, 0x80, 0x3C, 0x00]]);
+        let output = render(&data, |stream| {
+            stream.set_loop_count(3);
+            stream.set_duration(Some(1.2));
+        });
+        let seconds = ((output.len() - 44) / 4) as f64 / SAMPLE_RATE as f64;
+        assert!((1.2..1.3).contains(&seconds));
+    }
+
+    #[test]
+    fn duration_loops_with_a_fade() {
+        // A note held for the whole file, which is shorter than the duration,
+        // so that the limit is only reached after the sequence has looped.
+        let data = midi_file(&[&[0x00, 0x90, 0x3C, 0x64, 0x83, 0x60, 0x80, 0x3C, 0x00]]);
+        let mut stream = stream(&data, true);
+        stream.set_duration(Some(1.2));
+        let mut left = Vec::new();
+        while left.len() < 3 * SAMPLE_RATE as usize / 2 {
+            let (batch, _) = stream.get_data();
+            left.extend(batch.iter().step_by(2).copied());
+        }
+
+        let at = |seconds: f64| (seconds * SAMPLE_RATE as f64) as usize;
+        let peak = |range: Range<usize>| left[range].iter().map(|value| value.abs()).max();
+        // Silent at the limit, back at the start of the loop with nothing above
+        // the level faded out to, and then playing again.
+        let restart = left[at(1.2)..]
+            .iter()
+            .position(|&value| value != 0)
+            .unwrap()
+            + at(1.2);
+        assert!(peak(at(1.199)..at(1.2)) < Some(100));
+        assert!(left[restart].abs() < 100);
+        assert!(peak(at(1.35)..at(1.45)) > Some(1000));
+    }
+
+    #[test]
+    fn mono_ramps_in() {
+        // A note held for the whole file, panned all the way to the left.
+        let data = midi_file(&[&[0x00, 0x90, 0x3C, 0x64, 0x83, 0x60, 0x80, 0x3C, 0x00]]);
+        let mut stream = stream(&data, false);
+        stream.shared.mix.lock().unwrap().pan = -1_f32;
+        let mut samples = Vec::new();
+        while samples.len() < SAMPLE_RATE as usize / 2 {
+            samples.extend_from_slice(stream.get_data().0);
+        }
+        assert!(samples[samples.len() - 64..]
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .all(|&value| value == 0));
+
+        // The right channel comes in gradually instead of stepping to the mix.
+        stream.shared.mix.lock().unwrap().mono = true;
+        let mut right = Vec::new();
+        let mut left = Vec::new();
+        while right.len() < ramp_length(SAMPLE_RATE) + 64 {
+            let (batch, _) = stream.get_data();
+            left.extend(batch.iter().step_by(2).copied());
+            right.extend(batch.iter().skip(1).step_by(2).copied());
+        }
+        let peak = |samples: &[i16]| samples.iter().map(|value| value.abs()).max().unwrap();
+        assert!(peak(&right[..16]) < 100);
+        let end = right.len() - 64..;
+        assert!(peak(&right[end.clone()]) > 1000);
+        assert!(right[end.clone()] == left[end]);
+    }
+
+    #[test]
+    fn wav_names_stay_apart() {
+        let paths = ["a.mid", "a.midi", "b.mid"].map(PathBuf::from);
+        let wav_paths: Vec<_> = wav_paths(&paths, Path::new("out"))
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            wav_paths,
+            ["out/a.mid.wav", "out/a.midi.wav", "out/b.wav"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn empty_file_ends() {
+        let data = midi_file(&[&[]]);
+        let (midi_file, midi_events) = parse_midi_file(&data).unwrap();
+        assert_eq!(midi_file.get_length(), 0_f64);
+        assert_eq!(format_length(midi_file.get_length()), "unknown length");
+
+        let sound_font = Arc::new(SoundFont::new(&mut &SOUND_FONT[..]).unwrap());
+        let mut sequencer =
+            MidiMusicStream::create_sequencer(&sound_font, SAMPLE_RATE, 64, true).unwrap();
+        sequencer.play(&Arc::new(midi_events), false);
+        let mut left = vec![0_f32; 64];
+        let mut right = vec![0_f32; 64];
+        sequencer.render(&mut left, &mut right);
+        assert!(sequencer.end_of_sequence());
+
+        // The render stops once the tail after the end has been rendered,
+        // give or take a batch.
+        let output = render(&data, |_| ());
+        let seconds = ((output.len() - 44) / 4) as f64 / SAMPLE_RATE as f64;
+        assert!(seconds <= END_TAIL_LENGTH + 0.1);
+    }
+}
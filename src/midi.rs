@@ -0,0 +1,705 @@
+use rustysynth::MidiFile;
+use rustysynth::MidiFileError;
+use std::io::Cursor;
+
+// The events of a standard MIDI file, merged across tracks and timed in seconds.
+//
+// rustysynth's MidiFile doesn't expose its messages, so the file is read a
+// second time here to let the sequencer inspect them. The data is expected to
+// have been validated by MidiFile::new already, so malformed input is read as
+// far as possible instead of being reported.
+
+#[derive(Clone, Copy, Debug)]
+pub enum Message {
+    Channel {
+        channel: u8,
+        command: u8,
+        data1: u8,
+        data2: u8,
+    },
+    // A GS or XG system exclusive message switching a part between
+    // a drum kit and a melodic instrument.
+    DrumPart {
+        channel: u8,
+        drums: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Event {
+    pub time: f64,
+    pub message: Message,
+}
+
+pub struct MidiEvents {
+    pub events: Vec<Event>,
+    pub metadata: Metadata,
+    pub track_count: usize,
+    // In time order, for finding the beat at a given time.
+    tempo_changes: Vec<TempoChange>,
+    // In beat order, for finding the bar at a given beat.
+    meter_changes: Vec<MeterChange>,
+    // The number of ticks per quarter note.
+    resolution: u16,
+}
+
+#[derive(Clone, Copy)]
+struct TempoChange {
+    time: f64,
+    // The number of quarter notes since the start.
+    beat: f64,
+    tempo: u32,
+}
+
+// A time signature taking effect, which always starts a new bar.
+#[derive(Clone, Copy)]
+struct MeterChange {
+    // The number of quarter notes and of whole bars since the start.
+    beat: f64,
+    bar: u64,
+    numerator: u8,
+    denominator: u32,
+}
+
+// A position in musical time, with the bar and the beat counted from 1.
+#[derive(Clone, Copy)]
+pub struct BarPosition {
+    pub bar: u64,
+    pub beat: u32,
+    // The ticks of the file since the start of the beat.
+    pub tick: u32,
+}
+
+// The descriptive meta events, with the first occurrence of each kind in file order
+// taken as the initial one.
+#[derive(Clone, Default)]
+pub struct Metadata {
+    pub track_names: Vec<String>,
+    pub copyright: Option<String>,
+    // In microseconds per quarter note.
+    pub tempo: Option<u32>,
+    // The numerator and the denominator.
+    pub time_signature: Option<(u8, u32)>,
+    // The number of sharps, or flats if negative, and whether it's minor.
+    pub key_signature: Option<(i8, bool)>,
+}
+
+impl Metadata {
+    pub fn get_bpm(&self) -> f64 {
+        60_000_000_f64 / self.tempo.unwrap_or(MidiEvents::DEFAULT_TEMPO) as f64
+    }
+
+    pub fn get_key_name(&self) -> Option<String> {
+        const MAJOR: [&str; 15] = [
+            "Cb", "Gb", "Db", "Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E", "B", "F#", "C#",
+        ];
+        const MINOR: [&str; 15] = [
+            "Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E", "B", "F#", "C#", "G#", "D#", "A#",
+        ];
+        let (sharps, minor) = self.key_signature?;
+        let index = (sharps.clamp(-7, 7) + 7) as usize;
+        Some(if minor {
+            format!("{} minor", MINOR[index])
+        } else {
+            format!("{} major", MAJOR[index])
+        })
+    }
+
+    // The tempo with the time and key signatures that are known, on one line.
+    pub fn get_summary(&self) -> String {
+        let mut summary = format!("{:.1} BPM", self.get_bpm());
+        if let Some((numerator, denominator)) = self.time_signature {
+            summary.push_str(&format!(" {}/{}", numerator, denominator));
+        }
+        if let Some(key) = self.get_key_name() {
+            summary.push_str(&format!(" {}", key));
+        }
+        summary
+    }
+}
+
+enum TrackEvent {
+    Message(Message),
+    Tempo(u32),
+    // The numerator and the denominator.
+    TimeSignature(u8, u32),
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data: data,
+            position: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn read_u8(&mut self) -> Option<u8> {
+        let value = *self.data.get(self.position)?;
+        self.position += 1;
+        Some(value)
+    }
+
+    fn peek_u8(&self) -> Option<u8> {
+        self.data.get(self.position).copied()
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        let end = self.position.checked_add(length)?;
+        let value = self.data.get(self.position..end)?;
+        self.position = end;
+        Some(value)
+    }
+
+    fn read_u16(&mut self) -> Option<u16> {
+        let bytes = self.read_bytes(2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_u32(&mut self) -> Option<u32> {
+        let bytes = self.read_bytes(4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_variable_length(&mut self) -> Option<u32> {
+        let mut value = 0_u32;
+        for _ in 0..4 {
+            let byte = self.read_u8()?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        Some(value)
+    }
+}
+
+impl MeterChange {
+    const DEFAULT: MeterChange = MeterChange {
+        beat: 0.0,
+        bar: 0,
+        numerator: 4,
+        denominator: 4,
+    };
+
+    // The length of a beat in quarter notes.
+    fn get_beat_length(&self) -> f64 {
+        4.0 / self.denominator as f64
+    }
+}
+
+impl MidiEvents {
+    const DEFAULT_TEMPO: u32 = 500000;
+
+    pub fn new(data: &[u8]) -> Self {
+        let mut reader = Reader::new(data);
+        let mut resolution = 480_u16;
+        let mut tracks = Vec::new();
+        let mut metadata = Metadata::default();
+
+        while !reader.is_empty() {
+            let Some(chunk_type) = reader.read_bytes(4) else {
+                break;
+            };
+            let Some(size) = reader.read_u32() else {
+                break;
+            };
+            let chunk_end = reader
+                .position
+                .saturating_add(size as usize)
+                .min(data.len());
+            let chunk = &data[reader.position..chunk_end];
+            reader.position = chunk_end;
+
+            match chunk_type {
+                b"MThd" => {
+                    let mut header = Reader::new(chunk);
+                    header.read_u16();
+                    header.read_u16();
+                    resolution = header.read_u16().unwrap_or(resolution).max(1);
+                }
+                b"MTrk" => tracks.push(MidiEvents::read_track(chunk, &mut metadata)),
+                _ => (),
+            }
+        }
+
+        let (events, tempo_changes, meter_changes) = MidiEvents::merge_tracks(&tracks, resolution);
+
+        Self {
+            events: events,
+            metadata: metadata,
+            track_count: tracks.len(),
+            tempo_changes: tempo_changes,
+            meter_changes: meter_changes,
+            resolution: resolution,
+        }
+    }
+
+    // The number of quarter notes played by the given time in seconds,
+    // following the tempo changes.
+    pub fn get_beat(&self, time: f64) -> f64 {
+        let index = self
+            .tempo_changes
+            .partition_point(|change| change.time <= time);
+        let change = match index {
+            0 => TempoChange {
+                time: 0.0,
+                beat: 0.0,
+                tempo: MidiEvents::DEFAULT_TEMPO,
+            },
+            _ => self.tempo_changes[index - 1],
+        };
+        change.beat + (time - change.time) * 1_000_000_f64 / change.tempo.max(1) as f64
+    }
+
+    // The bar, the beat and the tick played at the given time in seconds, following
+    // the tempo and time signature changes, with 4/4 until the first time signature.
+    pub fn get_bar_position(&self, time: f64) -> BarPosition {
+        let beat = self.get_beat(time);
+        let index = self
+            .meter_changes
+            .partition_point(|change| change.beat <= beat);
+        let change = match index {
+            0 => MeterChange::DEFAULT,
+            _ => self.meter_changes[index - 1],
+        };
+        let beat_length = change.get_beat_length();
+        let bar_length = change.numerator as f64 * beat_length;
+        let offset = (beat - change.beat).max(0.0);
+        let bars = (offset / bar_length).floor();
+        let in_bar = offset - bars * bar_length;
+        let beats = ((in_bar / beat_length).floor() as u32).min(change.numerator as u32 - 1);
+        let in_beat = in_bar - beats as f64 * beat_length;
+        BarPosition {
+            bar: change.bar + bars as u64 + 1,
+            beat: beats + 1,
+            tick: (in_beat * self.resolution as f64) as u32,
+        }
+    }
+
+    // The slowest and the fastest tempo in BPM, counting the default one
+    // when it's in effect before the first tempo change.
+    pub fn get_tempo_range(&self) -> (f64, f64) {
+        let changed_at_start = self
+            .tempo_changes
+            .first()
+            .is_some_and(|change| change.time <= 0.0);
+        let tempos = self
+            .tempo_changes
+            .iter()
+            .map(|change| change.tempo.max(1))
+            .chain((!changed_at_start).then_some(MidiEvents::DEFAULT_TEMPO));
+        let (slowest, fastest) = tempos.fold((u32::MIN, u32::MAX), |(slowest, fastest), tempo| {
+            (slowest.max(tempo), fastest.min(tempo))
+        });
+        (
+            60_000_000_f64 / slowest as f64,
+            60_000_000_f64 / fastest as f64,
+        )
+    }
+
+    // The number of notes started in each of the given number of equal parts
+    // of the length, scaled so that the busiest part is 1.
+    pub fn get_note_density(&self, length: f64, bucket_count: usize) -> Vec<f32> {
+        let mut density = vec![0_f32; bucket_count];
+        if length <= 0.0 || bucket_count == 0 {
+            return density;
+        }
+        for event in &self.events {
+            if let Message::Channel {
+                command: 0x90,
+                data2: 1..,
+                ..
+            } = event.message
+            {
+                let index = (event.time / length * bucket_count as f64) as usize;
+                density[index.min(bucket_count - 1)] += 1_f32;
+            }
+        }
+        let max = density.iter().fold(0_f32, |a, &b| a.max(b));
+        if max > 0_f32 {
+            density.iter_mut().for_each(|value| *value /= max);
+        }
+        density
+    }
+
+    fn read_track(data: &[u8], metadata: &mut Metadata) -> Vec<(u64, TrackEvent)> {
+        let mut reader = Reader::new(data);
+        let mut events = Vec::new();
+        let mut tick = 0_u64;
+        let mut running_status = 0_u8;
+
+        while let Some(delta) = reader.read_variable_length() {
+            tick += delta as u64;
+
+            let Some(first) = reader.peek_u8() else {
+                break;
+            };
+            let status = if first & 0x80 != 0 {
+                reader.position += 1;
+                first
+            } else {
+                running_status
+            };
+
+            match status {
+                0xFF => {
+                    let (Some(kind), Some(length)) =
+                        (reader.read_u8(), reader.read_variable_length())
+                    else {
+                        break;
+                    };
+                    let Some(bytes) = reader.read_bytes(length as usize) else {
+                        break;
+                    };
+                    let text = || String::from_utf8_lossy(bytes).trim().to_string();
+                    match kind {
+                        0x2F => break,
+                        0x02 if metadata.copyright.is_none() => metadata.copyright = Some(text()),
+                        0x03 if !text().is_empty() => metadata.track_names.push(text()),
+                        0x51 if bytes.len() == 3 => {
+                            let tempo = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+                            metadata.tempo.get_or_insert(tempo);
+                            events.push((tick, TrackEvent::Tempo(tempo)));
+                        }
+                        // The denominator is stored as a power of two.
+                        0x58 if bytes.len() >= 2 => {
+                            let denominator = 1_u32.checked_shl(bytes[1] as u32).unwrap_or(0);
+                            metadata
+                                .time_signature
+                                .get_or_insert((bytes[0], denominator));
+                            if bytes[0] > 0 && denominator > 0 {
+                                let time_signature =
+                                    TrackEvent::TimeSignature(bytes[0], denominator);
+                                events.push((tick, time_signature));
+                            }
+                        }
+                        0x59 if bytes.len() >= 2 => {
+                            metadata
+                                .key_signature
+                                .get_or_insert((bytes[0] as i8, bytes[1] != 0));
+                        }
+                        _ => (),
+                    }
+                }
+                0xF0 | 0xF7 => {
+                    let Some(length) = reader.read_variable_length() else {
+                        break;
+                    };
+                    let Some(bytes) = reader.read_bytes(length as usize) else {
+                        break;
+                    };
+                    if let Some(message) = MidiEvents::read_drum_part(status, bytes) {
+                        events.push((tick, TrackEvent::Message(message)));
+                    }
+                }
+                0x80..=0xEF => {
+                    running_status = status;
+                    let command = status & 0xF0;
+                    let Some(data1) = reader.read_u8() else {
+                        break;
+                    };
+                    let data2 = if command == 0xC0 || command == 0xD0 {
+                        0
+                    } else {
+                        match reader.read_u8() {
+                            Some(value) => value,
+                            None => break,
+                        }
+                    };
+                    let message = Message::Channel {
+                        channel: status & 0x0F,
+                        command: command,
+                        data1: data1,
+                        data2: data2,
+                    };
+                    events.push((tick, TrackEvent::Message(message)));
+                }
+                // A data byte without a preceding status can't be interpreted.
+                _ => break,
+            }
+        }
+
+        events
+    }
+
+    // Recognizes the GS "use for rhythm part" and the XG "part mode" parameters,
+    // given the bytes following the status.
+    fn read_drum_part(status: u8, bytes: &[u8]) -> Option<Message> {
+        if status != 0xF0 {
+            return None;
+        }
+        match bytes {
+            // The GS parts are numbered with the rhythm part first, and the data
+            // is followed by a checksum making the address and the data add up to 0.
+            [0x41, _, 0x42, 0x12, 0x40, part @ 0x10..=0x1F, 0x15, map, sum, ..] => {
+                if !(0x40 + *part as u32 + 0x15 + *map as u32 + *sum as u32).is_multiple_of(128) {
+                    return None;
+                }
+                let channel = match part & 0x0F {
+                    0 => 9,
+                    part @ 1..=9 => part - 1,
+                    part => part,
+                };
+                Some(Message::DrumPart {
+                    channel: channel,
+                    drums: *map != 0,
+                })
+            }
+            [0x43, 0x10..=0x1F, 0x4C, 0x08, part @ 0x00..=0x0F, 0x07, mode, ..] => {
+                Some(Message::DrumPart {
+                    channel: *part,
+                    drums: *mode != 0,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    // Merges the tracks in tick order, converting the ticks to seconds with
+    // the tempo changes found along the way, and counting the bars with
+    // the time signatures. Ties go to the earlier track.
+    fn merge_tracks(
+        tracks: &[Vec<(u64, TrackEvent)>],
+        resolution: u16,
+    ) -> (Vec<Event>, Vec<TempoChange>, Vec<MeterChange>) {
+        let mut indices = vec![0_usize; tracks.len()];
+        let mut events = Vec::new();
+        let mut tempo_changes = Vec::new();
+        let mut meter_changes: Vec<MeterChange> = Vec::new();
+        let mut tempo = MidiEvents::DEFAULT_TEMPO;
+        let mut current_tick = 0_u64;
+        let mut current_time = 0_f64;
+
+        loop {
+            let mut next: Option<usize> = None;
+            for (i, track) in tracks.iter().enumerate() {
+                if let Some((tick, _)) = track.get(indices[i]) {
+                    if next.is_none_or(|n| *tick < tracks[n][indices[n]].0) {
+                        next = Some(i);
+                    }
+                }
+            }
+            let Some(i) = next else {
+                break;
+            };
+
+            let (tick, event) = &tracks[i][indices[i]];
+            current_time +=
+                (tick - current_tick) as f64 * tempo as f64 / (1_000_000_f64 * resolution as f64);
+            current_tick = *tick;
+
+            match event {
+                TrackEvent::Message(message) => events.push(Event {
+                    time: current_time,
+                    message: *message,
+                }),
+                TrackEvent::Tempo(value) => {
+                    tempo = *value;
+                    tempo_changes.push(TempoChange {
+                        time: current_time,
+                        beat: current_tick as f64 / resolution as f64,
+                        tempo: tempo,
+                    });
+                }
+                TrackEvent::TimeSignature(numerator, denominator) => {
+                    let beat = current_tick as f64 / resolution as f64;
+                    let last = meter_changes
+                        .last()
+                        .copied()
+                        .unwrap_or(MeterChange::DEFAULT);
+                    // A bar cut short by the change still counts as one.
+                    let bar_length = last.numerator as f64 * last.get_beat_length();
+                    let bars = ((beat - last.beat) / bar_length - 1e-9).ceil().max(0.0);
+                    let change = MeterChange {
+                        beat: beat,
+                        bar: last.bar + bars as u64,
+                        numerator: *numerator,
+                        denominator: *denominator,
+                    };
+                    // Of the changes on the same tick, the last one is kept.
+                    match meter_changes.last_mut() {
+                        Some(last) if last.beat == beat => *last = change,
+                        _ => meter_changes.push(change),
+                    }
+                }
+            }
+
+            indices[i] += 1;
+        }
+
+        (events, tempo_changes, meter_changes)
+    }
+}
+
+// The standard MIDI file inside an RMID file, which wraps it in the "data" chunk
+// of a RIFF container. Anything else is taken to be a standard MIDI file already,
+// as is an RMID file without that chunk, for MidiFile::new to report.
+pub fn unwrap_rmid(data: &[u8]) -> &[u8] {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"RMID" {
+        return data;
+    }
+
+    // The chunks are padded to an even length, which the sizes don't include.
+    let mut position = 12;
+    while let Some(header) = data.get(position..position + 8) {
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let start = position + 8;
+        let end = start.saturating_add(size).min(data.len());
+        if &header[0..4] == b"data" {
+            return &data[start..end];
+        }
+        position = end + (size & 1);
+    }
+    data
+}
+
+// The header and the tracks of a standard MIDI file, as the contents of their chunks.
+// A chunk running past the end of the data gets what's left of it.
+pub fn split_tracks(data: &[u8]) -> Option<(&[u8], Vec<&[u8]>)> {
+    let mut reader = Reader::new(data);
+    let mut header = None;
+    let mut tracks = Vec::new();
+    while !reader.is_empty() {
+        let (Some(chunk_type), Some(size)) = (reader.read_bytes(4), reader.read_u32()) else {
+            break;
+        };
+        let end = reader
+            .position
+            .saturating_add(size as usize)
+            .min(data.len());
+        let chunk = &data[reader.position..end];
+        reader.position = end;
+        match chunk_type {
+            b"MThd" if header.is_none() => header = Some(chunk),
+            b"MTrk" => tracks.push(chunk),
+            _ => (),
+        }
+    }
+    Some((header?, tracks))
+}
+
+// A track left out by drop_unreadable_tracks, numbered from 0 out of the tracks in the file.
+pub struct DroppedTrack {
+    pub index: usize,
+    pub count: usize,
+    pub error: MidiFileError,
+}
+
+// The file without the tracks that MidiFile::new can't read on their own, or None
+// when there's no header or no track left, along with the tracks left out.
+pub fn drop_unreadable_tracks(data: &[u8]) -> (Option<Vec<u8>>, Vec<DroppedTrack>) {
+    let Some((header, tracks)) = split_tracks(data) else {
+        return (None, Vec::new());
+    };
+    let mut readable = Vec::new();
+    let mut dropped = Vec::new();
+    for (index, track) in tracks.iter().enumerate() {
+        let single = join_tracks(header, &[track]);
+        match MidiFile::new(&mut Cursor::new(&single[..])) {
+            Ok(_) => readable.push(*track),
+            Err(error) => dropped.push(DroppedTrack {
+                index: index,
+                count: tracks.len(),
+                error: error,
+            }),
+        }
+    }
+    let data = (!readable.is_empty()).then(|| join_tracks(header, &readable));
+    (data, dropped)
+}
+
+// A standard MIDI file with the given header and tracks, and the number of tracks
+// in the header changed to match.
+pub fn join_tracks(header: &[u8], tracks: &[&[u8]]) -> Vec<u8> {
+    let mut header = header.to_vec();
+    if let Some(count) = header.get_mut(2..4) {
+        count.copy_from_slice(&(tracks.len() as u16).to_be_bytes());
+    }
+    let mut data = Vec::new();
+    for (chunk_type, chunk) in [(b"MThd", &header[..])]
+        .into_iter()
+        .chain(tracks.iter().map(|track| (b"MTrk", *track)))
+    {
+        data.extend_from_slice(chunk_type);
+        data.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+        data.extend_from_slice(chunk);
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A format 1 file at 480 ticks per beat with two tracks, a tempo and a note.
+    fn smf() -> Vec<u8> {
+        let header = [0x00, 0x01, 0x00, 0x02, 0x01, 0xE0];
+        let tempo: &[u8] = &[
+            0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, 0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let note: &[u8] = &[
+            0x00, 0x90, 0x3C, 0x64, 0x83, 0x60, 0x80, 0x3C, 0x00, 0x00, 0xFF, 0x2F, 0x00,
+        ];
+        join_tracks(&header, &[tempo, note])
+    }
+
+    // A RIFF chunk of the given type with its contents, padded to an even length.
+    fn riff_chunk(chunk_type: &[u8], contents: &[u8]) -> Vec<u8> {
+        let mut chunk = chunk_type.to_vec();
+        chunk.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(contents);
+        if contents.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    #[test]
+    fn unwraps_rmid() {
+        let smf = smf();
+        // An odd-sized chunk ahead of the data, to step over its padding.
+        let mut contents = b"RMID".to_vec();
+        contents.extend_from_slice(&riff_chunk(b"LIST", b"INFOx"));
+        contents.extend_from_slice(&riff_chunk(b"data", &smf));
+        let rmid = riff_chunk(b"RIFF", &contents);
+        assert_eq!(unwrap_rmid(&rmid), &smf[..]);
+    }
+
+    #[test]
+    fn drops_truncated_track() {
+        // The note track loses its last bytes, the end of track among them.
+        let mut data = smf();
+        data.truncate(data.len() - 5);
+        let (header, tracks) = split_tracks(&data).unwrap();
+        assert_eq!(header, [0x00, 0x01, 0x00, 0x02, 0x01, 0xE0]);
+        assert_eq!(tracks.len(), 2);
+
+        let (readable, dropped) = drop_unreadable_tracks(&data);
+        let dropped: Vec<_> = dropped
+            .iter()
+            .map(|track| (track.index, track.count))
+            .collect();
+        assert_eq!(dropped, [(1, 2)]);
+        let readable = readable.unwrap();
+        let smf = smf();
+        let (_, original_tracks) = split_tracks(&smf).unwrap();
+        let (_, tracks) = split_tracks(&readable).unwrap();
+        assert_eq!(tracks, [original_tracks[0]]);
+        assert!(MidiFile::new(&mut Cursor::new(&readable[..])).is_ok());
+    }
+
+    #[test]
+    fn passes_smf_through() {
+        let smf = smf();
+        assert_eq!(unwrap_rmid(&smf), &smf[..]);
+    }
+}
@@ -0,0 +1,79 @@
+use crate::log::info;
+use crate::midi::Message;
+use midir::Ignore;
+use midir::MidiInput;
+use midir::MidiInputConnection;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+const CLIENT_NAME: &str = "rustysynth-test";
+
+// Opens the input port given by its number or a part of its name, and collects
+// the channel messages it receives for the stream to play on its next batch.
+// The port stays open for as long as the returned connection is kept.
+pub fn connect(
+    port_name: &str,
+    messages: Arc<Mutex<Vec<Message>>>,
+) -> Result<MidiInputConnection<()>, String> {
+    let mut input = MidiInput::new(CLIENT_NAME)
+        .map_err(|error| format!("Failed to open the MIDI input: {}", error))?;
+    // System exclusive, timing and active sensing messages mean nothing to the synthesizer.
+    input.ignore(Ignore::All);
+
+    let ports = input.ports();
+    let names: Vec<String> = ports
+        .iter()
+        .map(|port| input.port_name(port).unwrap_or_default())
+        .collect();
+    let lower = port_name.to_lowercase();
+    let index = port_name
+        .parse::<usize>()
+        .ok()
+        .filter(|&index| index < ports.len())
+        .or_else(|| {
+            names
+                .iter()
+                .position(|name| name.to_lowercase().contains(&lower))
+        });
+    let Some(index) = index else {
+        let mut message = format!("MIDI input port '{}' was not found.", port_name);
+        if names.is_empty() {
+            message.push_str(" No ports are available.");
+        } else {
+            message.push_str(" The available ports are:");
+            for (index, name) in names.iter().enumerate() {
+                message.push_str(&format!("\n  {}: {}", index, name));
+            }
+        }
+        return Err(message);
+    };
+
+    let name = &names[index];
+    info!("Listening to MIDI input '{}'.", name);
+    input
+        .connect(
+            &ports[index],
+            CLIENT_NAME,
+            move |_, data, _| {
+                if let Some(message) = parse_message(data) {
+                    messages.lock().unwrap().push(message);
+                }
+            },
+            (),
+        )
+        .map_err(|error| format!("Failed to connect to MIDI input '{}': {}", name, error))
+}
+
+// midir hands over each message whole, with its status byte even under running status.
+fn parse_message(data: &[u8]) -> Option<Message> {
+    let (&status, rest) = data.split_first()?;
+    if !(0x80..0xF0).contains(&status) {
+        return None;
+    }
+    Some(Message::Channel {
+        channel: status & 0x0F,
+        command: status & 0xF0,
+        data1: rest.first().copied().unwrap_or(0),
+        data2: rest.get(1).copied().unwrap_or(0),
+    })
+}
@@ -0,0 +1,105 @@
+use crate::midi::Message;
+use sfml::window::Key;
+
+// Plays notes on the first channel from the bottom row of letters, laid out like
+// a tracker's with the sharps on the row above, and picks the program with the
+// number row. The held keys are tracked so that the key repeat doesn't retrigger them.
+pub struct NoteKeys {
+    held: [bool; NoteKeys::NOTE_KEYS.len()],
+}
+
+impl NoteKeys {
+    const CHANNEL: u8 = 0;
+    const VELOCITY: u8 = 100;
+    // The note of the Z key, the C in the middle of the piano.
+    const LOWEST_NOTE: u8 = 60;
+
+    // A semitone apart, from C to the E of the next octave.
+    const NOTE_KEYS: [Key; 17] = [
+        Key::Z,
+        Key::S,
+        Key::X,
+        Key::D,
+        Key::C,
+        Key::V,
+        Key::G,
+        Key::B,
+        Key::H,
+        Key::N,
+        Key::J,
+        Key::M,
+        Key::Comma,
+        Key::L,
+        Key::Period,
+        Key::Semicolon,
+        Key::Slash,
+    ];
+
+    // Programs 1 to 10 in the numbering of General MIDI.
+    const PROGRAM_KEYS: [Key; 10] = [
+        Key::Num1,
+        Key::Num2,
+        Key::Num3,
+        Key::Num4,
+        Key::Num5,
+        Key::Num6,
+        Key::Num7,
+        Key::Num8,
+        Key::Num9,
+        Key::Num0,
+    ];
+
+    pub fn new() -> Self {
+        Self {
+            held: [false; NoteKeys::NOTE_KEYS.len()],
+        }
+    }
+
+    // Adds the message of the key going down, and returns whether it's one of the keys,
+    // which then don't do what they would otherwise.
+    pub fn press(&mut self, code: Key, messages: &mut Vec<Message>) -> bool {
+        if let Some(index) = NoteKeys::PROGRAM_KEYS.iter().position(|&key| key == code) {
+            messages.push(NoteKeys::message(0xC0, index as u8, 0));
+            return true;
+        }
+        let Some(index) = NoteKeys::NOTE_KEYS.iter().position(|&key| key == code) else {
+            return false;
+        };
+        if !self.held[index] {
+            self.held[index] = true;
+            let note = NoteKeys::LOWEST_NOTE + index as u8;
+            messages.push(NoteKeys::message(0x90, note, NoteKeys::VELOCITY));
+        }
+        true
+    }
+
+    pub fn release(&mut self, code: Key, messages: &mut Vec<Message>) {
+        if let Some(index) = NoteKeys::NOTE_KEYS.iter().position(|&key| key == code) {
+            self.release_note(index, messages);
+        }
+    }
+
+    // The window doesn't hear the keys that go up while it's out of focus.
+    pub fn release_all(&mut self, messages: &mut Vec<Message>) {
+        for index in 0..NoteKeys::NOTE_KEYS.len() {
+            self.release_note(index, messages);
+        }
+    }
+
+    fn release_note(&mut self, index: usize, messages: &mut Vec<Message>) {
+        if self.held[index] {
+            self.held[index] = false;
+            let note = NoteKeys::LOWEST_NOTE + index as u8;
+            messages.push(NoteKeys::message(0x80, note, 0));
+        }
+    }
+
+    fn message(command: u8, data1: u8, data2: u8) -> Message {
+        Message::Channel {
+            channel: NoteKeys::CHANNEL,
+            command: command,
+            data1: data1,
+            data2: data2,
+        }
+    }
+}
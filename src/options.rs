@@ -0,0 +1,618 @@
+use std::ffi::OsString;
+use std::str::FromStr;
+
+pub struct Options {
+    pub sound_font_paths: Vec<OsString>,
+    pub midi_path: OsString,
+    pub play_loop: bool,
+    // The number of times to play each file, instead of looping forever.
+    pub loop_count: Option<u32>,
+    pub render_path: Option<OsString>,
+    // The directory of MIDI files to render and the one to put the WAV files in.
+    pub render_dir: Option<(OsString, OsString)>,
+    // Overrides the config file when given.
+    pub sample_rate: Option<u32>,
+    // The rate played or written at, which the output is resampled to when
+    // it differs from the one rendered at.
+    pub device_rate: Option<u32>,
+    // The multiple of the sample rate to synthesize at before filtering back down to it.
+    pub oversample: u32,
+    // The length of the batches in milliseconds, instead of the default for the backend.
+    pub latency: Option<u32>,
+    // How the samples of the SoundFonts are read between their points.
+    pub interpolation: Interpolation,
+    // In decibels, overriding the config file when given.
+    pub gain_db: Option<f32>,
+    pub polyphony: usize,
+    pub limiter: bool,
+    pub mono: bool,
+    // Take the DC offset out of the output with a high-pass at a few Hz.
+    pub dc_block: bool,
+    // The channels from 0 to 15 that start soloed, like with Shift and the number keys.
+    pub channels: Option<[bool; 16]>,
+    // Dither the samples before they're quantized to 16 bits.
+    pub dither: bool,
+    pub pcm: bool,
+    pub no_window: bool,
+    // Play without the window like --no-window, with level meters drawn in the terminal.
+    pub ascii: bool,
+    pub normalize: bool,
+    pub resume: bool,
+    // The position in seconds to start the first file at.
+    pub start: Option<f64>,
+    // The number of seconds to stop or loop after, counted from the start.
+    pub duration: Option<f64>,
+    // In milliseconds.
+    pub fade_in: u32,
+    // The MIDI input port to play live from, in which case there's no MIDI file.
+    pub midi_in: Option<String>,
+    // Play notes live from the computer keyboard, also without a MIDI file.
+    pub keyboard: bool,
+    // Overrides the config file when given.
+    pub theme: Option<String>,
+    // The names of two visualizations to show side by side.
+    pub split: Option<(String, String)>,
+    // Print what's in the MIDI file and exit, for which the SoundFonts are optional.
+    pub info: bool,
+    // Print the presets of the SoundFonts and exit, for which no MIDI file is needed.
+    pub list_presets: bool,
+    // Leave out the informational messages on stderr.
+    pub quiet: bool,
+    // Play the readable tracks of a MIDI file that can't be read as a whole.
+    pub lenient: bool,
+}
+
+// The ways of reading a sample between its points, from the cheapest to the smoothest.
+// rustysynth always interpolates linearly, with no setting for it, so that's the default
+// and the only one that can be used for now. The others are known so that asking for
+// them says why they aren't available instead of that the name is wrong.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    Nearest,
+    Linear,
+    Cubic,
+}
+
+impl Interpolation {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nearest" => Some(Interpolation::Nearest),
+            "linear" => Some(Interpolation::Linear),
+            "cubic" => Some(Interpolation::Cubic),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Interpolation::Nearest => "nearest",
+            Interpolation::Linear => "linear",
+            Interpolation::Cubic => "cubic",
+        }
+    }
+}
+
+impl Options {
+    const DEFAULT_POLYPHONY: usize = 64;
+    const DEFAULT_FADE_IN: u32 = 200;
+    const OVERSAMPLE_MAX: u32 = 4;
+    const LATENCY_MIN: u32 = 5;
+    const LATENCY_MAX: u32 = 200;
+
+    pub fn parse<I: Iterator<Item = OsString>>(mut args: I) -> Result<Self, String> {
+        let mut paths = Vec::new();
+        let mut play_loop = false;
+        let mut loop_count = None;
+        let mut render_path = None;
+        let mut render_dir = None;
+        let mut sample_rate = None;
+        let mut device_rate = None;
+        let mut oversample = 1;
+        let mut latency = None;
+        let mut gain_db = None;
+        let mut polyphony = Options::DEFAULT_POLYPHONY;
+        let mut limiter = false;
+        let mut mono = false;
+        let mut dc_block = false;
+        let mut channels = None;
+        let mut dither = false;
+        let mut pcm = false;
+        let mut no_window = false;
+        let mut ascii = false;
+        let mut normalize = false;
+        let mut resume = false;
+        let mut start = None;
+        let mut duration = None;
+        let mut fade_in = Options::DEFAULT_FADE_IN;
+        // Only set by '--midi-in', which is left out without the 'midi-in' feature.
+        #[cfg_attr(not(feature = "midi-in"), allow(unused_mut))]
+        let mut midi_in = None;
+        let mut theme = None;
+        let mut split = None;
+        let mut info = false;
+        let mut keyboard = false;
+        let mut quiet = false;
+        let mut lenient = false;
+        let mut interpolation = Interpolation::Linear;
+        let mut list_presets = false;
+
+        while let Some(arg) = args.next() {
+            match arg.to_str() {
+                Some("--loop") => play_loop = true,
+                Some("--limiter") => limiter = true,
+                Some("--mono") => mono = true,
+                Some("--dc-block") => dc_block = true,
+                Some("--dither") => dither = true,
+                Some("--pcm") => pcm = true,
+                Some("--no-window") => no_window = true,
+                Some("--ascii") => ascii = true,
+                Some("--normalize") => normalize = true,
+                Some("--resume") => resume = true,
+                Some("--info") => info = true,
+                Some("--keyboard") => keyboard = true,
+                Some("--quiet") => quiet = true,
+                Some("--lenient") => lenient = true,
+                Some("--list-presets") => list_presets = true,
+                Some("--render") => render_path = Some(next_arg(&mut args, "--render")?),
+                Some("--render-dir") => {
+                    let input = next_arg(&mut args, "--render-dir")?;
+                    let output = next_arg(&mut args, "--render-dir")?;
+                    render_dir = Some((input, output));
+                }
+                Some("--rate") => sample_rate = Some(parse_next_arg(&mut args, "--rate")?),
+                Some("--device-rate") => {
+                    device_rate = Some(parse_next_arg(&mut args, "--device-rate")?)
+                }
+                Some("--oversample") => {
+                    let factor: u32 = parse_next_arg(&mut args, "--oversample")?;
+                    if !(1..=Options::OVERSAMPLE_MAX).contains(&factor) {
+                        return Err(format!("Invalid value '{}' for '--oversample'.", factor));
+                    }
+                    oversample = factor;
+                }
+                Some("--latency") => {
+                    let milliseconds: u32 = parse_next_arg(&mut args, "--latency")?;
+                    if !(Options::LATENCY_MIN..=Options::LATENCY_MAX).contains(&milliseconds) {
+                        return Err(format!("Invalid value '{}' for '--latency'.", milliseconds));
+                    }
+                    latency = Some(milliseconds);
+                }
+                Some("--interp") => {
+                    let value = next_arg(&mut args, "--interp")?;
+                    interpolation = value
+                        .to_str()
+                        .and_then(Interpolation::from_name)
+                        .ok_or_else(|| {
+                            format!(
+                                "Invalid value '{}' for '--interp'.",
+                                value.to_string_lossy()
+                            )
+                        })?;
+                    if interpolation != Interpolation::Linear {
+                        return Err(format!(
+                            "'--interp {}' isn't available; rustysynth only interpolates linearly.",
+                            interpolation.name()
+                        ));
+                    }
+                }
+                Some("--gain") => {
+                    let db: f32 = parse_next_arg(&mut args, "--gain")?;
+                    if !db.is_finite() {
+                        return Err(format!("Invalid value '{}' for '--gain'.", db));
+                    }
+                    gain_db = Some(db);
+                }
+                Some("--theme") => {
+                    let name = next_arg(&mut args, "--theme")?;
+                    theme = Some(name.to_string_lossy().into_owned());
+                }
+                Some("--split") => {
+                    let first = next_arg(&mut args, "--split")?;
+                    let second = next_arg(&mut args, "--split")?;
+                    split = Some((
+                        first.to_string_lossy().into_owned(),
+                        second.to_string_lossy().into_owned(),
+                    ));
+                }
+                Some("--channels") => {
+                    let value = next_arg(&mut args, "--channels")?;
+                    let list = value.to_str().and_then(parse_channels).ok_or_else(|| {
+                        format!(
+                            "Invalid value '{}' for '--channels'.",
+                            value.to_string_lossy()
+                        )
+                    })?;
+                    channels = Some(list);
+                }
+                Some("--fade-in") => fade_in = parse_next_arg(&mut args, "--fade-in")?,
+                #[cfg(feature = "midi-in")]
+                Some("--midi-in") => {
+                    let port = next_arg(&mut args, "--midi-in")?;
+                    midi_in = Some(port.to_string_lossy().into_owned());
+                }
+                #[cfg(not(feature = "midi-in"))]
+                Some("--midi-in") => {
+                    return Err("'--midi-in' needs a build with the 'midi-in' feature.".to_string());
+                }
+                Some("--loop-count") => {
+                    let count: u32 = parse_next_arg(&mut args, "--loop-count")?;
+                    if count == 0 {
+                        return Err("Invalid value '0' for '--loop-count'.".to_string());
+                    }
+                    loop_count = Some(count);
+                }
+                Some("--polyphony") => polyphony = parse_next_arg(&mut args, "--polyphony")?,
+                Some("--start") => {
+                    let value = next_arg(&mut args, "--start")?;
+                    let seconds = value.to_str().and_then(parse_time).ok_or_else(|| {
+                        format!("Invalid value '{}' for '--start'.", value.to_string_lossy())
+                    })?;
+                    start = Some(seconds);
+                }
+                Some("--duration") => {
+                    let seconds: f64 = parse_next_arg(&mut args, "--duration")?;
+                    if !(seconds.is_finite() && seconds > 0.0) {
+                        return Err(format!("Invalid value '{}' for '--duration'.", seconds));
+                    }
+                    duration = Some(seconds);
+                }
+                Some(name) if name.starts_with("--") => {
+                    return Err(format!("Unknown argument '{}'.", name));
+                }
+                _ => paths.push(arg),
+            }
+        }
+
+        // The last path is the MIDI file and the preceding ones are SoundFonts,
+        // unless playing live, rendering a directory or listing the presets,
+        // where they're all SoundFonts.
+        let midi_path = if midi_in.is_some() || keyboard || render_dir.is_some() || list_presets {
+            if paths.is_empty() {
+                return Err("Missing soundfont.".to_string());
+            }
+            OsString::new()
+        } else {
+            let Some(midi_path) = paths.pop() else {
+                return Err("Missing soundfont.".to_string());
+            };
+            if paths.is_empty() && !info {
+                return Err("Missing path to midi file.".to_string());
+            }
+            midi_path
+        };
+        // stdin can only be read once.
+        if paths
+            .iter()
+            .chain([&midi_path])
+            .filter(|path| *path == "-")
+            .count()
+            > 1
+        {
+            return Err("Only one path can be '-'.".to_string());
+        }
+        if play_loop && render_path.is_some() {
+            return Err("'--loop' can't be combined with '--render'.".to_string());
+        }
+        if play_loop && pcm {
+            return Err("'--loop' can't be combined with '--pcm'.".to_string());
+        }
+        if pcm && render_path.is_some() {
+            return Err("'--pcm' can't be combined with '--render'.".to_string());
+        }
+        if no_window && render_path.is_some() {
+            return Err("'--no-window' can't be combined with '--render'.".to_string());
+        }
+        if no_window && pcm {
+            return Err("'--no-window' can't be combined with '--pcm'.".to_string());
+        }
+        if ascii && render_path.is_some() {
+            return Err("'--ascii' can't be combined with '--render'.".to_string());
+        }
+        if ascii && pcm {
+            return Err("'--ascii' can't be combined with '--pcm'.".to_string());
+        }
+        if ascii && no_window {
+            return Err("'--ascii' can't be combined with '--no-window'.".to_string());
+        }
+        if resume && render_path.is_some() {
+            return Err("'--resume' can't be combined with '--render'.".to_string());
+        }
+        if resume && pcm {
+            return Err("'--resume' can't be combined with '--pcm'.".to_string());
+        }
+        if midi_in.is_some() && play_loop {
+            return Err("'--midi-in' can't be combined with '--loop'.".to_string());
+        }
+        if midi_in.is_some() && render_path.is_some() {
+            return Err("'--midi-in' can't be combined with '--render'.".to_string());
+        }
+        if midi_in.is_some() && pcm {
+            return Err("'--midi-in' can't be combined with '--pcm'.".to_string());
+        }
+        if midi_in.is_some() && normalize {
+            return Err("'--midi-in' can't be combined with '--normalize'.".to_string());
+        }
+        if midi_in.is_some() && resume {
+            return Err("'--midi-in' can't be combined with '--resume'.".to_string());
+        }
+        if keyboard && play_loop {
+            return Err("'--keyboard' can't be combined with '--loop'.".to_string());
+        }
+        if keyboard && render_path.is_some() {
+            return Err("'--keyboard' can't be combined with '--render'.".to_string());
+        }
+        if keyboard && pcm {
+            return Err("'--keyboard' can't be combined with '--pcm'.".to_string());
+        }
+        if keyboard && no_window {
+            return Err("'--keyboard' can't be combined with '--no-window'.".to_string());
+        }
+        if keyboard && ascii {
+            return Err("'--keyboard' can't be combined with '--ascii'.".to_string());
+        }
+        if keyboard && normalize {
+            return Err("'--keyboard' can't be combined with '--normalize'.".to_string());
+        }
+        if midi_in.is_some() && lenient {
+            return Err("'--midi-in' can't be combined with '--lenient'.".to_string());
+        }
+        if keyboard && lenient {
+            return Err("'--keyboard' can't be combined with '--lenient'.".to_string());
+        }
+        if keyboard && resume {
+            return Err("'--keyboard' can't be combined with '--resume'.".to_string());
+        }
+        if info && render_path.is_some() {
+            return Err("'--info' can't be combined with '--render'.".to_string());
+        }
+        if info && pcm {
+            return Err("'--info' can't be combined with '--pcm'.".to_string());
+        }
+        if info && midi_in.is_some() {
+            return Err("'--info' can't be combined with '--midi-in'.".to_string());
+        }
+        if info && keyboard {
+            return Err("'--info' can't be combined with '--keyboard'.".to_string());
+        }
+        if info && render_dir.is_some() {
+            return Err("'--info' can't be combined with '--render-dir'.".to_string());
+        }
+        if list_presets && render_path.is_some() {
+            return Err("'--list-presets' can't be combined with '--render'.".to_string());
+        }
+        if list_presets && pcm {
+            return Err("'--list-presets' can't be combined with '--pcm'.".to_string());
+        }
+        if list_presets && midi_in.is_some() {
+            return Err("'--list-presets' can't be combined with '--midi-in'.".to_string());
+        }
+        if list_presets && keyboard {
+            return Err("'--list-presets' can't be combined with '--keyboard'.".to_string());
+        }
+        if list_presets && render_dir.is_some() {
+            return Err("'--list-presets' can't be combined with '--render-dir'.".to_string());
+        }
+        if list_presets && info {
+            return Err("'--list-presets' can't be combined with '--info'.".to_string());
+        }
+        if loop_count.is_some() && play_loop {
+            return Err("'--loop-count' can't be combined with '--loop'.".to_string());
+        }
+        if loop_count.is_some() && midi_in.is_some() {
+            return Err("'--loop-count' can't be combined with '--midi-in'.".to_string());
+        }
+        if loop_count.is_some() && keyboard {
+            return Err("'--loop-count' can't be combined with '--keyboard'.".to_string());
+        }
+        if start.is_some() && resume {
+            return Err("'--start' can't be combined with '--resume'.".to_string());
+        }
+        if start.is_some() && midi_in.is_some() {
+            return Err("'--start' can't be combined with '--midi-in'.".to_string());
+        }
+        if start.is_some() && keyboard {
+            return Err("'--start' can't be combined with '--keyboard'.".to_string());
+        }
+        if start.is_some() && render_dir.is_some() {
+            return Err("'--start' can't be combined with '--render-dir'.".to_string());
+        }
+        if render_dir.is_some() && play_loop {
+            return Err("'--render-dir' can't be combined with '--loop'.".to_string());
+        }
+        if render_dir.is_some() && render_path.is_some() {
+            return Err("'--render-dir' can't be combined with '--render'.".to_string());
+        }
+        if render_dir.is_some() && pcm {
+            return Err("'--render-dir' can't be combined with '--pcm'.".to_string());
+        }
+        if render_dir.is_some() && no_window {
+            return Err("'--render-dir' can't be combined with '--no-window'.".to_string());
+        }
+        if render_dir.is_some() && ascii {
+            return Err("'--render-dir' can't be combined with '--ascii'.".to_string());
+        }
+        if render_dir.is_some() && normalize {
+            return Err("'--render-dir' can't be combined with '--normalize'.".to_string());
+        }
+        if render_dir.is_some() && resume {
+            return Err("'--render-dir' can't be combined with '--resume'.".to_string());
+        }
+        if render_dir.is_some() && midi_in.is_some() {
+            return Err("'--render-dir' can't be combined with '--midi-in'.".to_string());
+        }
+        if render_dir.is_some() && keyboard {
+            return Err("'--render-dir' can't be combined with '--keyboard'.".to_string());
+        }
+
+        Ok(Self {
+            sound_font_paths: paths,
+            midi_path: midi_path,
+            play_loop: play_loop,
+            loop_count: loop_count,
+            render_path: render_path,
+            render_dir: render_dir,
+            sample_rate: sample_rate,
+            device_rate: device_rate,
+            oversample: oversample,
+            latency: latency,
+            gain_db: gain_db,
+            polyphony: polyphony,
+            limiter: limiter,
+            mono: mono,
+            dc_block: dc_block,
+            channels: channels,
+            dither: dither,
+            pcm: pcm,
+            no_window: no_window,
+            ascii: ascii,
+            normalize: normalize,
+            resume: resume,
+            start: start,
+            duration: duration,
+            fade_in: fade_in,
+            midi_in: midi_in,
+            keyboard: keyboard,
+            theme: theme,
+            split: split,
+            info: info,
+            list_presets: list_presets,
+            quiet: quiet,
+            lenient: lenient,
+            interpolation: interpolation,
+        })
+    }
+
+    // Whether the notes come in as they're played instead of from a MIDI file.
+    pub fn is_live(&self) -> bool {
+        self.midi_in.is_some() || self.keyboard
+    }
+
+    // Whether the sequence is rendered without opening the window.
+    pub fn is_headless(&self) -> bool {
+        self.render_path.is_some() || self.render_dir.is_some() || self.pcm
+    }
+
+    // Whether the MIDI file or a soundfont is read from stdin, which leaves it at the end.
+    pub fn reads_stdin(&self) -> bool {
+        self.sound_font_paths
+            .iter()
+            .chain([&self.midi_path])
+            .any(|path| path == "-")
+    }
+}
+
+pub fn print_usage() {
+    eprintln!("Usage: rustysynth-test <soundfont>... <midi-file-or-directory> [options]");
+    eprintln!();
+    eprintln!("A directory plays the MIDI files in it in name order.");
+    eprintln!("A path of '-' reads the file from stdin, which can be done for one file only.");
+    eprintln!(
+        "With '--midi-in', '--render-dir' or '--list-presets', the paths are all SoundFonts."
+    );
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("  --info           Print what's in the MIDI files and SoundFonts, and exit.");
+    eprintln!("  --list-presets   Print the SoundFonts' presets as bank:program:name and exit.");
+    eprintln!("  --loop           Loop the playback.");
+    eprintln!("  --loop-count <n> Play each file the given number of times, then stop.");
+    eprintln!("  --render <wav>   Render to a WAV file instead of playing.");
+    eprintln!("  --render-dir <midi-dir> <wav-dir>");
+    eprintln!("                   Render each MIDI file in a directory to a WAV file in another.");
+    eprintln!("  --pcm            Write raw 16-bit little-endian stereo PCM to stdout instead.");
+    eprintln!("  --no-window      Play without the window; type 'p' to pause and 'q' to quit.");
+    eprintln!("  --ascii          Play without the window like --no-window, with level meters.");
+    eprintln!("  --rate <hz>      Set the sample rate (16000-192000, default: 44100).");
+    eprintln!("  --device-rate <hz>");
+    eprintln!("                   Resample the output to this rate (default: the device's own).");
+    eprintln!("  --oversample <n> Synthesize at n times the rate and filter back down (1-4).");
+    eprintln!("  --latency <ms>   Render in batches of this length (5-200), shorter for quicker");
+    eprintln!("                   controls but more risk of running out of samples.");
+    eprintln!(
+        "  --interp <mode>  Set how the samples are interpolated (nearest, linear or cubic)."
+    );
+    eprintln!("                   rustysynth only has linear, the default, for now.");
+    eprintln!("  --gain <db>      Set the initial gain in decibels (-60 to 12).");
+    eprintln!("  --limiter        Soften the peaks near full scale instead of clipping them.");
+    eprintln!("  --mono           Output the average of the left and right channels on both.");
+    eprintln!("  --dc-block       Remove the DC offset with a high-pass at 5 Hz.");
+    eprintln!("  --channels <list>");
+    eprintln!("                   Solo the channels in a list like 1,2,10. The solo keys then");
+    eprintln!("                   toggle them like any other solo, and the mute keys only take");
+    eprintln!("                   effect once no channel is soloed.");
+    eprintln!("  --dither         Add triangular dither of 1 LSB before quantizing to 16 bits.");
+    eprintln!("  --resume         Continue the file from where it was left off last time.");
+    eprintln!("  --normalize      Scan the tracks first and play each with its peak at -1 dBFS.");
+    eprintln!("  --polyphony <n>  Set the maximum number of voices (8-256, default: 64).");
+    eprintln!("  --start <time>   Start at the given seconds or minutes:seconds into the file.");
+    eprintln!("  --duration <s>   Play for the given seconds, then stop or go back to the start.");
+    eprintln!("  --theme <name>   Set the color theme (ocean, mono, fire or matrix).");
+    eprintln!("  --split <a> <b>  Show two visualizations side by side, like waveform spectrum.");
+    eprintln!("  --fade-in <ms>   Fade in from silence at the start (default: 200, 0 for none).");
+    if cfg!(feature = "midi-in") {
+        eprintln!("  --midi-in <port> Play live from a MIDI input port, given by number or name.");
+    }
+    eprintln!("  --keyboard       Play notes on the keys from Z, with 1-0 picking the program.");
+    eprintln!("  --quiet          Only print errors, warnings and prompts to stderr.");
+    eprintln!("  --lenient        Skip the tracks that can't be read instead of the whole file.");
+}
+
+// Reads a comma-separated list of channels from 1 to 16 into the states of 0 to 15.
+fn parse_channels(value: &str) -> Option<[bool; 16]> {
+    let mut channels = [false; 16];
+    for part in value.split(',') {
+        let channel: usize = part.trim().parse().ok()?;
+        if !(1..=16).contains(&channel) {
+            return None;
+        }
+        channels[channel - 1] = true;
+    }
+    Some(channels)
+}
+
+// Reads seconds, "minutes:seconds" or "hours:minutes:seconds",
+// where only the seconds may have a fraction.
+fn parse_time(value: &str) -> Option<f64> {
+    let parts: Vec<&str> = value.split(':').collect();
+    let (seconds, units) = parts.split_last()?;
+    if units.len() > 2 {
+        return None;
+    }
+    let seconds: f64 = seconds.parse().ok()?;
+    // Past the first part, each has to stay below a whole unit of the one before.
+    let limit = if units.is_empty() {
+        f64::INFINITY
+    } else {
+        60.0
+    };
+    if !(seconds.is_finite() && (0.0..limit).contains(&seconds)) {
+        return None;
+    }
+    let mut total = 0;
+    for (index, unit) in units.iter().enumerate() {
+        let value: u64 = unit.parse().ok()?;
+        if index > 0 && value >= 60 {
+            return None;
+        }
+        total = 60 * total + value;
+    }
+    Some(60.0 * total as f64 + seconds)
+}
+
+fn next_arg<I: Iterator<Item = OsString>>(args: &mut I, name: &str) -> Result<OsString, String> {
+    args.next()
+        .ok_or_else(|| format!("Missing value for '{}'.", name))
+}
+
+fn parse_next_arg<I: Iterator<Item = OsString>, T: FromStr>(
+    args: &mut I,
+    name: &str,
+) -> Result<T, String> {
+    let value = next_arg(args, name)?;
+    value.to_str().and_then(|s| s.parse().ok()).ok_or_else(|| {
+        format!(
+            "Invalid value '{}' for '{}'.",
+            value.to_string_lossy(),
+            name
+        )
+    })
+}
@@ -0,0 +1,86 @@
+use crate::config;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+// The playback positions left off at, kept by the absolute path of the MIDI file
+// in rustysynth-test-positions.toml in the user's config directory.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Positions {
+    positions: HashMap<String, f64>,
+    // So that the file is only written when there's something new in it.
+    #[serde(skip)]
+    changed: bool,
+}
+
+impl Positions {
+    const FILE_NAME: &'static str = "rustysynth-test-positions.toml";
+
+    // Having no file yet is not an error.
+    pub fn load() -> Result<Self, String> {
+        let Some(path) = Positions::path() else {
+            return Ok(Positions::default());
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return Ok(Positions::default())
+            }
+            Err(error) => return Err(format!("Failed to read '{}': {}", path.display(), error)),
+        };
+        toml::from_str(&text)
+            .map_err(|error| format!("Failed to parse '{}': {}", path.display(), error))
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        if !self.changed {
+            return Ok(());
+        }
+        let Some(path) = Positions::path() else {
+            return Err("No config directory to save the playback position in.".to_string());
+        };
+        let text = toml::to_string(self)
+            .map_err(|error| format!("Failed to serialize the playback positions: {}", error))?;
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|error| {
+                format!("Failed to create '{}': {}", directory.display(), error)
+            })?;
+        }
+        fs::write(&path, text)
+            .map_err(|error| format!("Failed to write '{}': {}", path.display(), error))
+    }
+
+    pub fn get(&self, midi_path: &Path) -> Option<f64> {
+        self.positions.get(&Positions::key(midi_path)?).copied()
+    }
+
+    // Saves the position, or forgets it when None.
+    pub fn set(&mut self, midi_path: &Path, position: Option<f64>) {
+        let Some(key) = Positions::key(midi_path) else {
+            return;
+        };
+        let previous = match position {
+            Some(position) => self.positions.insert(key, position),
+            None => self.positions.remove(&key),
+        };
+        self.changed |= previous != position;
+    }
+
+    fn path() -> Option<PathBuf> {
+        config::config_home().map(|config_home| config_home.join(Positions::FILE_NAME))
+    }
+
+    // Files read from stdin or that can no longer be found have no position.
+    fn key(midi_path: &Path) -> Option<String> {
+        if midi_path == Path::new("-") {
+            return None;
+        }
+        let path = fs::canonicalize(midi_path).ok()?;
+        Some(path.to_string_lossy().into_owned())
+    }
+}
@@ -0,0 +1,51 @@
+// A control that goes to each new setting in a straight line over a fixed number
+// of samples instead of jumping to it, which would step the signal and click.
+pub struct Ramp {
+    value: f32,
+    target: f32,
+    step: f32,
+    length: usize,
+}
+
+impl Ramp {
+    pub fn new(value: f32, length: usize) -> Self {
+        Self {
+            value: value,
+            target: value,
+            step: 0_f32,
+            length: length.max(1),
+        }
+    }
+
+    // Starts the ramp from where the value is now, if the target has changed.
+    pub fn set_target(&mut self, target: f32) {
+        if target != self.target {
+            self.target = target;
+            self.step = (target - self.value) / self.length as f32;
+        }
+    }
+
+    // The value for the next sample.
+    pub fn next(&mut self) -> f32 {
+        if self.value != self.target {
+            self.value += self.step;
+            let passed = match self.step > 0_f32 {
+                true => self.value >= self.target,
+                false => self.value <= self.target,
+            };
+            if passed {
+                self.value = self.target;
+            }
+        }
+        self.value
+    }
+
+    // Whether the value has arrived at the given one and stays there.
+    pub fn is_at(&self, value: f32) -> bool {
+        self.value == value && self.target == value
+    }
+
+    pub fn get_target(&self) -> f32 {
+        self.target
+    }
+}
@@ -0,0 +1,76 @@
+// Converts a stereo signal from one sample rate to another with cubic Hermite
+// interpolation. The last samples of each batch are kept for the next one,
+// along with the position between them, so that the batches join up seamlessly.
+pub struct Resampler {
+    // The number of input samples per output sample.
+    step: f64,
+    // The position of the next output sample in the buffers, which start
+    // with the samples kept from the previous batch.
+    position: f64,
+    buffers: [Vec<f32>; 2],
+}
+
+impl Resampler {
+    // The samples kept from the end of each batch: one before the interpolated point
+    // and two after it, as the cubic needs four around it.
+    const HISTORY_LENGTH: usize = 3;
+
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        Self {
+            step: input_rate as f64 / output_rate as f64,
+            position: 1.0,
+            buffers: [
+                vec![0_f32; Resampler::HISTORY_LENGTH],
+                vec![0_f32; Resampler::HISTORY_LENGTH],
+            ],
+        }
+    }
+
+    // Replaces the contents of the outputs with as many samples as the inputs
+    // make up to, which varies by one from batch to batch when the rates don't divide.
+    pub fn process(
+        &mut self,
+        left: &[f32],
+        right: &[f32],
+        output_left: &mut Vec<f32>,
+        output_right: &mut Vec<f32>,
+    ) {
+        for (buffer, input) in self.buffers.iter_mut().zip([left, right]) {
+            buffer.extend_from_slice(input);
+        }
+
+        output_left.clear();
+        output_right.clear();
+        let length = self.buffers[0].len();
+        loop {
+            let index = self.position as usize;
+            if index + 2 >= length {
+                break;
+            }
+            let fraction = (self.position - index as f64) as f32;
+            for (buffer, output) in self
+                .buffers
+                .iter()
+                .zip([&mut *output_left, &mut *output_right])
+            {
+                output.push(hermite(&buffer[index - 1..index + 3], fraction));
+            }
+            self.position += self.step;
+        }
+
+        let kept = length - Resampler::HISTORY_LENGTH;
+        for buffer in self.buffers.iter_mut() {
+            buffer.drain(..kept);
+        }
+        self.position -= kept as f64;
+    }
+}
+
+// Interpolates between the second and third of the four samples.
+fn hermite(samples: &[f32], fraction: f32) -> f32 {
+    let [y0, y1, y2, y3] = [samples[0], samples[1], samples[2], samples[3]];
+    let c1 = 0.5_f32 * (y2 - y0);
+    let c2 = y0 - 2.5_f32 * y1 + 2_f32 * y2 - 0.5_f32 * y3;
+    let c3 = 0.5_f32 * (y3 - y0) + 1.5_f32 * (y1 - y2);
+    ((c3 * fraction + c2) * fraction + c1) * fraction + y1
+}
@@ -0,0 +1,812 @@
+use crate::midi::Message;
+use crate::midi::MidiEvents;
+use rustysynth::Synthesizer;
+use std::cmp;
+use std::mem;
+use std::sync::Arc;
+
+// The bank and program of the preset forced on each channel, if any.
+pub type ProgramOverrides = [Option<(u8, u8)>; 16];
+
+// A channel message as it was sent to the synthesizer, after the transpose and
+// the overrides, with the time in seconds of the sequence it was sent at.
+#[derive(Clone, Copy)]
+pub struct SentMessage {
+    pub time: f64,
+    pub channel: u8,
+    pub command: u8,
+    pub data1: u8,
+    pub data2: u8,
+}
+
+// A MIDI file sequencer equivalent to rustysynth's MidiFileSequencer,
+// except that the messages pass through here on their way to the synthesizer
+// so that they can be observed or altered.
+pub struct Sequencer {
+    synthesizer: Synthesizer,
+    speed: f64,
+    midi_events: Option<Arc<MidiEvents>>,
+    play_loop: bool,
+    block_wrote: usize,
+    current_time: f64,
+    msg_index: usize,
+    // The key actually sent for each note being held, which differs from
+    // the key in the file when transposed.
+    notes: [[Option<u8>; 128]; 16],
+    transpose: i32,
+    // The tuning of all channels in cents.
+    fine_tune: i32,
+    // The registered parameter the file has selected on each channel, and the fine tuning
+    // it has given each one, which the tuning in cents is added to.
+    rpns: [(u8, u8); 16],
+    file_fine_tunes: [i32; 16],
+    loop_region: Option<(f64, f64)>,
+    reverb_send: Option<u8>,
+    chorus_send: Option<u8>,
+    muted: [bool; 16],
+    soloed: [bool; 16],
+    programs: [u8; 16],
+    // The bank selected by the file on each channel, and the bank and program
+    // forced on the channels where the file's own choice is overridden.
+    banks: [u8; 16],
+    program_overrides: ProgramOverrides,
+    // Whether each channel has played a note since the start or the last seek.
+    played: [bool; 16],
+    // Whether each channel plays drums, which aren't transposed or shown as pitches.
+    drums: [bool; 16],
+    // The highest velocity of the notes started on each channel since the last take.
+    velocities: [u8; 16],
+    // The running average of the note-on velocities, once a note has been played.
+    average_velocity: Option<f32>,
+    // The messages sent since the last take, while they're being logged.
+    sent_messages: Option<Vec<SentMessage>>,
+    tail_left: Vec<f32>,
+    tail_right: Vec<f32>,
+    tail_position: usize,
+}
+
+impl Sequencer {
+    // The length in seconds of the crossfade between the end and the beginning when looping.
+    const LOOP_CROSSFADE_LENGTH: f64 = 0.02;
+
+    pub const PERCUSSION_CHANNEL: u8 = 9;
+
+    const BANK_SELECT_CONTROLLER: u8 = 0;
+    // The banks that XG and GM2 use for the drum kits.
+    pub const DRUM_BANKS: [u8; 2] = [127, 120];
+
+    // How far the average velocity moves towards the velocity of each new note.
+    const VELOCITY_AVERAGING: f32 = 0.1_f32;
+
+    const DATA_ENTRY_CONTROLLER: u8 = 6;
+    const DATA_ENTRY_FINE_CONTROLLER: u8 = 38;
+    const NRPN_FINE_CONTROLLER: u8 = 98;
+    const NRPN_COARSE_CONTROLLER: u8 = 99;
+    const RPN_FINE_CONTROLLER: u8 = 100;
+    const RPN_COARSE_CONTROLLER: u8 = 101;
+    const RESET_ALL_CONTROLLERS: u8 = 121;
+    pub const ALL_SOUND_OFF: u8 = 120;
+    pub const ALL_NOTES_OFF: u8 = 123;
+    // The registered parameter of the fine tuning, and the null one that deselects it.
+    const FINE_TUNING_RPN: (u8, u8) = (0, 1);
+    const NULL_RPN: (u8, u8) = (127, 127);
+    // The fine tuning of a channel in tune, as 14 bits centered on 8192.
+    const CENTER_FINE_TUNE: i32 = 8192;
+
+    const REVERB_SEND_CONTROLLER: u8 = 91;
+    const CHORUS_SEND_CONTROLLER: u8 = 93;
+
+    // The send levels the synthesizer gives the channels when it's reset.
+    pub const DEFAULT_REVERB_SEND: u8 = 40;
+    pub const DEFAULT_CHORUS_SEND: u8 = 0;
+
+    pub fn new(synthesizer: Synthesizer) -> Self {
+        let block_size = synthesizer.get_block_size();
+
+        // The tail is rendered in whole blocks so that the events stay aligned to them.
+        let tail_length = Sequencer::LOOP_CROSSFADE_LENGTH * synthesizer.get_sample_rate() as f64
+            / block_size as f64;
+        let tail_length = block_size * (tail_length.ceil() as usize).max(1);
+
+        Self {
+            synthesizer: synthesizer,
+            speed: 1.0,
+            midi_events: None,
+            play_loop: false,
+            block_wrote: block_size,
+            current_time: 0.0,
+            msg_index: 0,
+            notes: [[None; 128]; 16],
+            transpose: 0,
+            fine_tune: 0,
+            rpns: [Sequencer::NULL_RPN; 16],
+            file_fine_tunes: [Sequencer::CENTER_FINE_TUNE; 16],
+            loop_region: None,
+            reverb_send: None,
+            chorus_send: None,
+            muted: [false; 16],
+            soloed: [false; 16],
+            programs: [0; 16],
+            banks: [0; 16],
+            program_overrides: [None; 16],
+            played: [false; 16],
+            drums: Sequencer::default_drums(),
+            velocities: [0; 16],
+            average_velocity: None,
+            sent_messages: None,
+            tail_left: vec![0_f32; tail_length],
+            tail_right: vec![0_f32; tail_length],
+            tail_position: tail_length,
+        }
+    }
+
+    pub fn play(&mut self, midi_events: &Arc<MidiEvents>, play_loop: bool) {
+        self.midi_events = Some(Arc::clone(midi_events));
+        self.play_loop = play_loop;
+        self.rewind();
+    }
+
+    fn rewind(&mut self) {
+        self.block_wrote = self.synthesizer.get_block_size();
+        self.current_time = 0.0;
+        self.msg_index = 0;
+        self.notes = [[None; 128]; 16];
+        self.programs = [0; 16];
+        self.banks = [0; 16];
+        self.played = [false; 16];
+        self.drums = Sequencer::default_drums();
+        self.rpns = [Sequencer::NULL_RPN; 16];
+        self.file_fine_tunes = [Sequencer::CENTER_FINE_TUNE; 16];
+        self.tail_position = self.tail_left.len();
+        self.synthesizer.reset();
+        self.apply_effect_sends();
+        self.apply_program_overrides();
+        self.apply_fine_tune();
+    }
+
+    // Moves the playback position to the given time in seconds.
+    // The messages before it are replayed except for the notes, so that
+    // programs and controllers are in the state they'd be at that point.
+    pub fn seek(&mut self, position: f64) {
+        self.rewind();
+
+        let Some(midi_events) = self.midi_events.clone() else {
+            return;
+        };
+        while let Some(event) = midi_events.events.get(self.msg_index) {
+            if event.time >= position {
+                break;
+            }
+            match event.message {
+                Message::Channel {
+                    channel,
+                    command,
+                    data1,
+                    data2,
+                } => {
+                    if command == 0xC0 {
+                        self.programs[channel as usize] = data1 & 0x7F;
+                    }
+                    self.follow_bank_select(channel, command, data1, data2);
+                    self.follow_rpn(channel, command, data1, data2);
+                    if command != 0x80
+                        && command != 0x90
+                        && !self.is_overridden(channel, command, data1)
+                    {
+                        self.synthesizer.process_midi_message(
+                            channel as i32,
+                            command as i32,
+                            data1 as i32,
+                            data2 as i32,
+                        );
+                    }
+                }
+                Message::DrumPart { channel, drums } => self.drums[channel as usize] = drums,
+            }
+            self.msg_index += 1;
+        }
+        self.apply_effect_sends();
+        self.apply_program_overrides();
+        self.apply_fine_tune();
+        self.current_time = position;
+    }
+
+    pub fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let block_size = self.synthesizer.get_block_size();
+        let sample_rate = self.synthesizer.get_sample_rate();
+
+        let left_length = left.len();
+        let mut wrote: usize = 0;
+        while wrote < left_length {
+            if self.block_wrote == block_size {
+                if let Some((start, end)) = self.loop_region {
+                    if self.current_time >= end {
+                        self.repeat_region(start);
+                    }
+                }
+                self.process_events();
+                self.block_wrote = 0;
+                self.current_time += self.speed * block_size as f64 / sample_rate as f64;
+            }
+
+            let src_rem = block_size - self.block_wrote;
+            let dst_rem = left_length - wrote;
+            let rem = cmp::min(src_rem, dst_rem);
+
+            self.synthesizer.render(
+                &mut left[wrote..wrote + rem],
+                &mut right[wrote..wrote + rem],
+            );
+            self.mix_tail(
+                &mut left[wrote..wrote + rem],
+                &mut right[wrote..wrote + rem],
+            );
+
+            self.block_wrote += rem;
+            wrote += rem;
+        }
+    }
+
+    fn process_events(&mut self) {
+        let Some(midi_events) = self.midi_events.clone() else {
+            return;
+        };
+
+        while let Some(event) = midi_events.events.get(self.msg_index) {
+            if event.time > self.current_time {
+                break;
+            }
+            self.send_message(event.message);
+            self.msg_index += 1;
+        }
+
+        if self.msg_index == midi_events.events.len() && self.play_loop {
+            self.start_over();
+        }
+    }
+
+    // Goes back to the beginning the way looping does, keeping the synthesizer as it is
+    // so that the reverb and chorus ring on into the next pass.
+    pub fn start_over(&mut self) {
+        // Render what would have followed the end, so that it can be faded out
+        // while the beginning fades in instead of cutting off with a click.
+        self.synthesizer
+            .render(&mut self.tail_left[..], &mut self.tail_right[..]);
+        self.tail_position = 0;
+
+        self.current_time = 0.0;
+        self.msg_index = 0;
+        self.notes = [[None; 128]; 16];
+        self.synthesizer.note_off_all(true);
+    }
+
+    // Goes back to the start of the loop region, crossfading like the loop at the end.
+    fn repeat_region(&mut self, start: f64) {
+        self.synthesizer
+            .render(&mut self.tail_left[..], &mut self.tail_right[..]);
+        self.seek(start);
+        self.tail_position = 0;
+    }
+
+    // Crossfades the rendered samples with the remaining tail from the previous loop.
+    fn mix_tail(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let tail_length = self.tail_left.len();
+        for t in 0..left.len() {
+            if self.tail_position == tail_length {
+                break;
+            }
+            let fade_in = self.tail_position as f32 / tail_length as f32;
+            let fade_out = 1_f32 - fade_in;
+            left[t] = fade_in * left[t] + fade_out * self.tail_left[self.tail_position];
+            right[t] = fade_in * right[t] + fade_out * self.tail_right[self.tail_position];
+            self.tail_position += 1;
+        }
+    }
+
+    fn send(&mut self, channel: u8, command: u8, mut data1: u8, mut data2: u8) {
+        let key = (data1 & 0x7F) as usize;
+
+        // The file's own send levels give way to the ones that have been set.
+        if command == 0xB0 {
+            let send = match data1 {
+                Sequencer::REVERB_SEND_CONTROLLER => self.reverb_send,
+                Sequencer::CHORUS_SEND_CONTROLLER => self.chorus_send,
+                _ => None,
+            };
+            if let Some(value) = send {
+                data2 = value;
+            }
+        }
+
+        match command {
+            0x90 if data2 > 0 => self.played[channel as usize] = true,
+            0xC0 => self.programs[channel as usize] = data1 & 0x7F,
+            _ => (),
+        }
+        self.follow_bank_select(channel, command, data1, data2);
+        if self.is_overridden(channel, command, data1) {
+            return;
+        }
+
+        // The file's own fine tuning is sent with the tuning in cents added to it.
+        if self.follow_rpn(channel, command, data1, data2) && self.fine_tune != 0 {
+            self.send_fine_tune(channel);
+            return;
+        }
+
+        // Muted channels only miss their note-ons, so that they come back
+        // with the right programs and controllers when unmuted.
+        if command == 0x90 && data2 > 0 && !self.is_audible(channel as usize) {
+            return;
+        }
+
+        // The note-offs and key pressures follow the key their note-on was sent with,
+        // so that changing the transpose doesn't leave notes hanging.
+        let held = &mut self.notes[channel as usize][key];
+        match command {
+            0x90 if data2 > 0 => {
+                let shift = if self.drums[channel as usize] {
+                    0
+                } else {
+                    self.transpose
+                };
+                let Ok(sent) = u8::try_from(key as i32 + shift) else {
+                    return;
+                };
+                if sent > 127 {
+                    return;
+                }
+                *held = Some(sent);
+                data1 = sent;
+                let velocity = &mut self.velocities[channel as usize];
+                *velocity = (*velocity).max(data2);
+                let average = self.average_velocity.unwrap_or(data2 as f32);
+                self.average_velocity =
+                    Some(average + Sequencer::VELOCITY_AVERAGING * (data2 as f32 - average));
+            }
+            0x80 | 0x90 => {
+                if let Some(sent) = held.take() {
+                    data1 = sent;
+                }
+            }
+            0xA0 => {
+                if let Some(sent) = *held {
+                    data1 = sent;
+                }
+            }
+            _ => (),
+        }
+
+        if let Some(sent_messages) = self.sent_messages.as_mut() {
+            sent_messages.push(SentMessage {
+                time: self.current_time,
+                channel: channel,
+                command: command,
+                data1: data1,
+                data2: data2,
+            });
+        }
+        self.synthesizer.process_midi_message(
+            channel as i32,
+            command as i32,
+            data1 as i32,
+            data2 as i32,
+        );
+
+        // The notes the channel was holding are gone, so nothing is left for their note-offs
+        // to follow if the key is played again with another transpose.
+        if command == 0xB0
+            && (data1 == Sequencer::ALL_SOUND_OFF || data1 == Sequencer::ALL_NOTES_OFF)
+        {
+            self.notes[channel as usize] = [None; 128];
+        }
+    }
+
+    // Sends a message from the file or from outside it, such as one played on
+    // a MIDI keyboard, with the same transpose and channel states either way.
+    pub fn send_message(&mut self, message: Message) {
+        match message {
+            Message::Channel {
+                channel,
+                command,
+                data1,
+                data2,
+            } => self.send(channel, command, data1, data2),
+            Message::DrumPart { channel, drums } => self.drums[channel as usize] = drums,
+        }
+    }
+
+    fn default_drums() -> [bool; 16] {
+        let mut drums = [false; 16];
+        drums[Sequencer::PERCUSSION_CHANNEL as usize] = true;
+        drums
+    }
+
+    // The drum banks of XG and GM2 make a channel play drums and the others make it
+    // melodic again, except on the percussion channel, where GS files select
+    // the melodic banks while still expecting drums.
+    fn follow_bank_select(&mut self, channel: u8, command: u8, data1: u8, data2: u8) {
+        if command != 0xB0 || data1 != Sequencer::BANK_SELECT_CONTROLLER {
+            return;
+        }
+        self.banks[channel as usize] = data2;
+        if Sequencer::DRUM_BANKS.contains(&data2) {
+            self.drums[channel as usize] = true;
+        } else if channel != Sequencer::PERCUSSION_CHANNEL {
+            self.drums[channel as usize] = false;
+        }
+    }
+
+    // Keeps track of the registered parameter selected on the channel and of the fine
+    // tuning set with it, and returns whether the message was a data entry for the latter.
+    // Selecting a non-registered parameter leaves the data entries to nothing in rustysynth,
+    // the same as the null one, and so does resetting the controllers, which leaves
+    // the tuning as it is.
+    fn follow_rpn(&mut self, channel: u8, command: u8, data1: u8, data2: u8) -> bool {
+        if command != 0xB0 {
+            return false;
+        }
+        let channel = channel as usize;
+        match data1 {
+            Sequencer::RPN_COARSE_CONTROLLER => self.rpns[channel].0 = data2,
+            Sequencer::RPN_FINE_CONTROLLER => self.rpns[channel].1 = data2,
+            Sequencer::NRPN_COARSE_CONTROLLER
+            | Sequencer::NRPN_FINE_CONTROLLER
+            | Sequencer::RESET_ALL_CONTROLLERS => self.rpns[channel] = Sequencer::NULL_RPN,
+            Sequencer::DATA_ENTRY_CONTROLLER | Sequencer::DATA_ENTRY_FINE_CONTROLLER
+                if self.rpns[channel] == Sequencer::FINE_TUNING_RPN =>
+            {
+                let tune = &mut self.file_fine_tunes[channel];
+                *tune = match data1 {
+                    Sequencer::DATA_ENTRY_CONTROLLER => (*tune & 0x7F) | (data2 as i32) << 7,
+                    _ => (*tune & !0x7F) | data2 as i32,
+                };
+                return true;
+            }
+            _ => (),
+        }
+        false
+    }
+
+    // Whether the message is a bank select or program change from the file that
+    // gives way to the preset forced on the channel.
+    fn is_overridden(&self, channel: u8, command: u8, data1: u8) -> bool {
+        let selects =
+            command == 0xC0 || (command == 0xB0 && data1 == Sequencer::BANK_SELECT_CONTROLLER);
+        selects && self.program_overrides[channel as usize].is_some()
+    }
+
+    // Solo takes precedence: while any channel is soloed, only the soloed ones sound.
+    fn is_audible(&self, channel: usize) -> bool {
+        if self.soloed.iter().any(|&on| on) {
+            self.soloed[channel]
+        } else {
+            !self.muted[channel]
+        }
+    }
+
+    pub fn set_channel_states(&mut self, muted: [bool; 16], soloed: [bool; 16]) {
+        if muted == self.muted && soloed == self.soloed {
+            return;
+        }
+        self.muted = muted;
+        self.soloed = soloed;
+
+        // Release the notes held on the channels that have just been silenced.
+        for channel in 0..16 {
+            if self.is_audible(channel) {
+                continue;
+            }
+            for key in 0..128 {
+                if let Some(sent) = self.notes[channel][key].take() {
+                    self.synthesizer.note_off(channel as i32, sent as i32);
+                }
+            }
+        }
+    }
+
+    // Shifts the notes played from now on by the given number of semitones,
+    // except on the percussion channel.
+    pub fn set_transpose(&mut self, value: i32) {
+        self.transpose = value;
+    }
+
+    // Tunes all channels by the given number of cents, up to a semitone either way.
+    // It's sent as the fine tuning parameter of each channel, added to the one the file
+    // gives the channel, and the transpose shifts the keys instead, so the two add up
+    // without touching the coarse tuning the file may also set.
+    pub fn set_fine_tune(&mut self, cents: i32) {
+        if cents == self.fine_tune {
+            return;
+        }
+        self.fine_tune = cents;
+        for channel in 0..16 {
+            self.send_fine_tune(channel);
+        }
+    }
+
+    // The file's own fine tuning has been sent as it is, for instance while seeking,
+    // so there's only something to send when detuned.
+    fn apply_fine_tune(&mut self) {
+        if self.fine_tune != 0 {
+            for channel in 0..16 {
+                self.send_fine_tune(channel);
+            }
+        }
+    }
+
+    // The value is 14 bits centered on 8192, where each semitone is 8192.
+    // The parameter the file had selected is selected again afterwards, so that
+    // its next data entries go where it meant them to.
+    fn send_fine_tune(&mut self, channel: u8) {
+        let file_fine_tune = self.file_fine_tunes[channel as usize];
+        let value = (file_fine_tune + self.fine_tune * 8192 / 100).clamp(0, 16383);
+        let rpn = self.rpns[channel as usize];
+        let controllers = [
+            (
+                Sequencer::RPN_COARSE_CONTROLLER,
+                Sequencer::FINE_TUNING_RPN.0 as i32,
+            ),
+            (
+                Sequencer::RPN_FINE_CONTROLLER,
+                Sequencer::FINE_TUNING_RPN.1 as i32,
+            ),
+            (Sequencer::DATA_ENTRY_CONTROLLER, value >> 7),
+            (Sequencer::DATA_ENTRY_FINE_CONTROLLER, value & 0x7F),
+            (Sequencer::RPN_COARSE_CONTROLLER, rpn.0 as i32),
+            (Sequencer::RPN_FINE_CONTROLLER, rpn.1 as i32),
+        ];
+        for (controller, value) in controllers {
+            self.synthesizer
+                .process_midi_message(channel as i32, 0xB0, controller as i32, value);
+        }
+    }
+
+    // Repeats the part between the given positions in seconds until cleared with None.
+    pub fn set_loop_region(&mut self, value: Option<(f64, f64)>) {
+        self.loop_region = value;
+    }
+
+    // Sets the reverb and chorus send levels of all channels from 0 to 127,
+    // or lets the file decide them again with None.
+    // rustysynth has no master controls for them, so they're sent as control changes.
+    pub fn set_effect_sends(&mut self, reverb: Option<u8>, chorus: Option<u8>) {
+        if reverb == self.reverb_send && chorus == self.chorus_send {
+            return;
+        }
+        self.reverb_send = reverb;
+        self.chorus_send = chorus;
+        self.apply_effect_sends();
+    }
+
+    fn apply_effect_sends(&mut self) {
+        let sends = [
+            (Sequencer::REVERB_SEND_CONTROLLER, self.reverb_send),
+            (Sequencer::CHORUS_SEND_CONTROLLER, self.chorus_send),
+        ];
+        for (controller, send) in sends {
+            let Some(value) = send else {
+                continue;
+            };
+            for channel in 0..16 {
+                self.synthesizer.process_midi_message(
+                    channel,
+                    0xB0,
+                    controller as i32,
+                    value as i32,
+                );
+            }
+        }
+    }
+
+    // Forces the bank and program of the SoundFont preset on each channel,
+    // or lets the file decide them again with None, in which case the file's
+    // last selection is sent back.
+    pub fn set_program_overrides(&mut self, overrides: ProgramOverrides) {
+        if overrides == self.program_overrides {
+            return;
+        }
+        for channel in 0..16 {
+            if self.program_overrides[channel].is_some() && overrides[channel].is_none() {
+                self.select_preset(channel as u8, self.banks[channel], self.programs[channel]);
+            }
+        }
+        self.program_overrides = overrides;
+        self.apply_program_overrides();
+    }
+
+    fn apply_program_overrides(&mut self) {
+        for channel in 0..16 {
+            if let Some((bank, program)) = self.program_overrides[channel] {
+                self.select_preset(channel as u8, bank, program);
+            }
+        }
+    }
+
+    // rustysynth adds 128 to the bank on the percussion channel, where the drum kits
+    // of bank 128 are selected with bank 0. The other channels take bank 128 as it is.
+    fn select_preset(&mut self, channel: u8, bank: u8, program: u8) {
+        let bank = if channel == Sequencer::PERCUSSION_CHANNEL {
+            bank.saturating_sub(128)
+        } else {
+            bank
+        };
+        self.synthesizer.process_midi_message(
+            channel as i32,
+            0xB0,
+            Sequencer::BANK_SELECT_CONTROLLER as i32,
+            bank as i32,
+        );
+        self.synthesizer
+            .process_midi_message(channel as i32, 0xC0, program as i32, 0);
+    }
+
+    pub fn get_synthesizer(&self) -> &Synthesizer {
+        &self.synthesizer
+    }
+
+    pub fn get_position(&self) -> f64 {
+        self.current_time
+    }
+
+    pub fn end_of_sequence(&self) -> bool {
+        match &self.midi_events {
+            None => true,
+            Some(value) => self.msg_index == value.events.len(),
+        }
+    }
+
+    pub fn get_speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, value: f64) {
+        self.speed = value;
+    }
+
+    // The channel holding each key as it was sent to the synthesizer, if any.
+    // The drum channels are left out, as their keys select drums rather than pitches.
+    pub fn get_held_keys(&self) -> [Option<u8>; 128] {
+        let mut keys = [None; 128];
+        for (channel, notes) in self.notes.iter().enumerate() {
+            if self.drums[channel] {
+                continue;
+            }
+            for sent in notes.iter().flatten() {
+                keys[*sent as usize] = Some(channel as u8);
+            }
+        }
+        keys
+    }
+
+    pub fn get_drum_channels(&self) -> [bool; 16] {
+        self.drums
+    }
+
+    // The current program of each channel that has played a note,
+    // which is the forced one where the file's is overridden.
+    pub fn get_programs(&self) -> [Option<u8>; 16] {
+        let mut programs = [None; 16];
+        for channel in 0..16 {
+            if self.played[channel] {
+                let program = self.program_overrides[channel].map(|(_, program)| program);
+                programs[channel] = Some(program.unwrap_or(self.programs[channel]));
+            }
+        }
+        programs
+    }
+
+    // The highest velocity of the notes started on each channel since the last call,
+    // which catches the short notes that are over before they're seen held.
+    pub fn take_velocities(&mut self) -> [u8; 16] {
+        mem::take(&mut self.velocities)
+    }
+
+    // Starts or stops keeping the messages sent to the synthesizer. The ones replayed
+    // by a seek aren't kept, as they're only there to bring back the state.
+    pub fn set_message_logging(&mut self, value: bool) {
+        if value != self.sent_messages.is_some() {
+            self.sent_messages = value.then(Vec::new);
+        }
+    }
+
+    // The messages sent since the last call, while they're being logged.
+    pub fn take_sent_messages(&mut self) -> Vec<SentMessage> {
+        self.sent_messages
+            .as_mut()
+            .map(mem::take)
+            .unwrap_or_default()
+    }
+
+    pub fn get_average_velocity(&self) -> Option<f32> {
+        self.average_velocity
+    }
+
+    // The number of notes being held on each channel.
+    pub fn get_channel_note_counts(&self) -> [usize; 16] {
+        let mut counts = [0; 16];
+        for (count, keys) in counts.iter_mut().zip(self.notes.iter()) {
+            *count = keys.iter().filter(|key| key.is_some()).count();
+        }
+        counts
+    }
+
+    // The number of notes that have been switched on and not yet off.
+    pub fn get_active_note_count(&self) -> usize {
+        self.notes
+            .iter()
+            .map(|keys| keys.iter().filter(|key| key.is_some()).count())
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustysynth::SoundFont;
+    use rustysynth::SynthesizerSettings;
+
+    const SOUND_FONT: &[u8] = include_bytes!("../resources/tests/sine.sf2");
+
+    const SAMPLE_RATE: i32 = 44100;
+
+    // Without the effects, which would blur the pitch of the sine.
+    fn sequencer() -> Sequencer {
+        let sound_font = Arc::new(SoundFont::new(&mut &SOUND_FONT[..]).unwrap());
+        let mut settings = SynthesizerSettings::new(SAMPLE_RATE);
+        settings.enable_reverb_and_chorus = false;
+        Sequencer::new(Synthesizer::new(&sound_font, &settings).unwrap())
+    }
+
+    fn control_change(sequencer: &mut Sequencer, controller: u8, value: u8) {
+        sequencer.send_message(Message::Channel {
+            channel: 0,
+            command: 0xB0,
+            data1: controller,
+            data2: value,
+        });
+    }
+
+    // Renders a second of the notes being held and counts the times the left channel
+    // goes from below zero to above it, which is the frequency of a single sine.
+    fn frequency(sequencer: &mut Sequencer) -> usize {
+        let mut left = vec![0_f32; SAMPLE_RATE as usize];
+        let mut right = vec![0_f32; SAMPLE_RATE as usize];
+        sequencer.render(&mut left, &mut right);
+        left.windows(2)
+            .filter(|pair| pair[0] < 0_f32 && pair[1] >= 0_f32)
+            .count()
+    }
+
+    #[test]
+    fn fine_tune_adds_to_the_file() {
+        let mut sequencer = sequencer();
+        // A4 held throughout, which is at 440 Hz as it is.
+        sequencer.send_message(Message::Channel {
+            channel: 0,
+            command: 0x90,
+            data1: 69,
+            data2: 100,
+        });
+        assert!((439..=441).contains(&frequency(&mut sequencer)));
+        sequencer.set_fine_tune(50);
+
+        // The file tunes the channel up by a quarter of a semitone and keeps
+        // the parameter selected.
+        control_change(&mut sequencer, Sequencer::RPN_COARSE_CONTROLLER, 0);
+        control_change(&mut sequencer, Sequencer::RPN_FINE_CONTROLLER, 1);
+        control_change(&mut sequencer, Sequencer::DATA_ENTRY_CONTROLLER, 80);
+        control_change(&mut sequencer, Sequencer::DATA_ENTRY_FINE_CONTROLLER, 0);
+        assert_eq!(sequencer.file_fine_tunes[0], 80 << 7);
+        assert_eq!(sequencer.rpns[0], Sequencer::FINE_TUNING_RPN);
+        // Both together are 75 cents up, at 459.5 Hz.
+        assert!((458..=461).contains(&frequency(&mut sequencer)));
+
+        // Resetting the controllers deselects the parameter and keeps the tuning.
+        control_change(&mut sequencer, Sequencer::RESET_ALL_CONTROLLERS, 0);
+        control_change(&mut sequencer, Sequencer::DATA_ENTRY_CONTROLLER, 64);
+        assert_eq!(sequencer.file_fine_tunes[0], 80 << 7);
+        assert_eq!(sequencer.rpns[0], Sequencer::NULL_RPN);
+        assert!((458..=461).contains(&frequency(&mut sequencer)));
+    }
+}
@@ -0,0 +1,52 @@
+use sfml::graphics::Color;
+
+// The colors of the window, selected by name with '--theme' or cycled through with Y.
+pub struct Theme {
+    pub name: &'static str,
+    pub background: Color,
+    // A fixed waveform color, or None to follow the activity.
+    pub waveform: Option<Color>,
+    // The filled part of the progress bar.
+    pub accent: Color,
+}
+
+pub const THEMES: [Theme; 4] = [
+    Theme {
+        name: "ocean",
+        background: Color::rgb(0, 32, 64),
+        waveform: None,
+        accent: Color::rgb(0, 150, 255),
+    },
+    Theme {
+        name: "mono",
+        background: Color::rgb(16, 16, 16),
+        waveform: Some(Color::rgb(220, 220, 220)),
+        accent: Color::rgb(160, 160, 160),
+    },
+    Theme {
+        name: "fire",
+        background: Color::rgb(40, 8, 0),
+        waveform: Some(Color::rgb(255, 140, 0)),
+        accent: Color::rgb(255, 60, 0),
+    },
+    Theme {
+        name: "matrix",
+        background: Color::rgb(0, 10, 0),
+        waveform: Some(Color::rgb(0, 255, 70)),
+        accent: Color::rgb(0, 180, 50),
+    },
+];
+
+// The accent of the colors set in the config file instead of a theme.
+pub const DEFAULT_ACCENT: Color = Color::rgb(0, 150, 255);
+
+pub fn find_theme(name: &str) -> Option<usize> {
+    THEMES
+        .iter()
+        .position(|theme| theme.name.eq_ignore_ascii_case(name))
+}
+
+pub fn theme_names() -> String {
+    let names: Vec<&str> = THEMES.iter().map(|theme| theme.name).collect();
+    names.join(", ")
+}
@@ -0,0 +1,61 @@
+use std::io;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+
+// Writes 16-bit stereo PCM samples to a WAV stream.
+// The sizes in the header are filled in by finalize.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    data_length: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    const CHANNEL_COUNT: u16 = 2;
+    const BITS_PER_SAMPLE: u16 = 16;
+
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        let block_align = WavWriter::<W>::CHANNEL_COUNT * WavWriter::<W>::BITS_PER_SAMPLE / 8;
+        let byte_rate = sample_rate * block_align as u32;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0_u32.to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16_u32.to_le_bytes())?;
+        writer.write_all(&1_u16.to_le_bytes())?;
+        writer.write_all(&WavWriter::<W>::CHANNEL_COUNT.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&byte_rate.to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&WavWriter::<W>::BITS_PER_SAMPLE.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0_u32.to_le_bytes())?;
+
+        Ok(Self {
+            writer: writer,
+            data_length: 0,
+        })
+    }
+
+    // Writes interleaved samples.
+    pub fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(2 * samples.len());
+        for sample in samples {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        self.writer.write_all(&bytes)?;
+        self.data_length += bytes.len() as u32;
+        Ok(())
+    }
+
+    // Patches the chunk sizes in the header.
+    pub fn finalize(mut self) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer
+            .write_all(&(36 + self.data_length).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_length.to_le_bytes())?;
+        self.writer.flush()
+    }
+}